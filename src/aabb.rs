@@ -37,4 +37,9 @@ impl AABB {
                              self.max.z.max(box1.max.z))
         }
     }
+
+    pub fn padded(&self, amount: f64) -> AABB {
+        let v = Vector3::new(amount, amount, amount);
+        AABB { min: self.min - v, max: self.max + v }
+    }
 }
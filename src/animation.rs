@@ -0,0 +1,199 @@
+use cgmath::{Point3, Quaternion, Vector3};
+use cgmath::prelude::*;
+use instance::Transform;
+
+/// How values between two keyframes are computed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    /// Cubic Bézier segments whose handles are placed automatically from the neighbouring
+    /// keys (Catmull-Rom), giving a smooth curve through all the keys.
+    Bezier,
+}
+
+/// Values that can be keyframed.
+pub trait Animatable: Copy {
+    fn lerp(self, other: Self, amount: f64) -> Self;
+
+    /// Point on the Bézier segment from `p1` to `p2`, with handles derived from `p0` and `p3`.
+    fn bezier(p0: Self, p1: Self, p2: Self, p3: Self, amount: f64) -> Self;
+}
+
+impl Animatable for f64 {
+    fn lerp(self, other: f64, amount: f64) -> f64 {
+        self + (other - self) * amount
+    }
+
+    fn bezier(p0: f64, p1: f64, p2: f64, p3: f64, amount: f64) -> f64 {
+        let c1 = p1 + (p2 - p0) / 6.0;
+        let c2 = p2 - (p3 - p1) / 6.0;
+        cubic_bezier(p1, c1, c2, p2, amount)
+    }
+}
+
+impl Animatable for Vector3<f64> {
+    fn lerp(self, other: Vector3<f64>, amount: f64) -> Vector3<f64> {
+        self + (other - self) * amount
+    }
+
+    fn bezier(p0: Vector3<f64>, p1: Vector3<f64>, p2: Vector3<f64>, p3: Vector3<f64>, amount: f64) -> Vector3<f64> {
+        Vector3::new(
+            f64::bezier(p0.x, p1.x, p2.x, p3.x, amount),
+            f64::bezier(p0.y, p1.y, p2.y, p3.y, amount),
+            f64::bezier(p0.z, p1.z, p2.z, p3.z, amount),
+        )
+    }
+}
+
+impl Animatable for Point3<f64> {
+    fn lerp(self, other: Point3<f64>, amount: f64) -> Point3<f64> {
+        Point3::from_vec(Animatable::lerp(self.to_vec(), other.to_vec(), amount))
+    }
+
+    fn bezier(p0: Point3<f64>, p1: Point3<f64>, p2: Point3<f64>, p3: Point3<f64>, amount: f64) -> Point3<f64> {
+        Point3::from_vec(Vector3::bezier(p0.to_vec(), p1.to_vec(), p2.to_vec(), p3.to_vec(), amount))
+    }
+}
+
+/// Rotations are always interpolated along the shortest arc with slerp, also when the
+/// keyframes ask for Bézier interpolation.
+impl Animatable for Quaternion<f64> {
+    fn lerp(self, other: Quaternion<f64>, amount: f64) -> Quaternion<f64> {
+        let other = if self.dot(other) < 0.0 { -other } else { other };
+        self.slerp(other, amount)
+    }
+
+    fn bezier(_p0: Quaternion<f64>, p1: Quaternion<f64>, p2: Quaternion<f64>, _p3: Quaternion<f64>, amount: f64) -> Quaternion<f64> {
+        Animatable::lerp(p1, p2, amount)
+    }
+}
+
+fn cubic_bezier(p0: f64, p1: f64, p2: f64, p3: f64, t: f64) -> f64 {
+    let s = 1.0 - t;
+    s * s * s * p0 + 3.0 * s * s * t * p1 + 3.0 * s * t * t * p2 + t * t * t * p3
+}
+
+/// Value that changes over time. Before the first and after the last key the value is held.
+#[derive(Clone)]
+pub struct Keyframes<T: Animatable> {
+    keys: Vec<(f64, T)>,
+    interpolation: Interpolation,
+}
+
+impl<T: Animatable> Keyframes<T> {
+    pub fn new(interpolation: Interpolation) -> Keyframes<T> {
+        Keyframes { keys: Vec::new(), interpolation }
+    }
+
+    pub fn constant(value: T) -> Keyframes<T> {
+        Keyframes::new(Interpolation::Linear).key(0.0, value)
+    }
+
+    /// Adds a key at `time`, keeping the keys sorted.
+    pub fn key(mut self, time: f64, value: T) -> Keyframes<T> {
+        let index = self.keys.iter().position(|&(t, _)| t > time).unwrap_or(self.keys.len());
+        self.keys.insert(index, (time, value));
+        self
+    }
+
+    pub fn times(&self) -> Vec<f64> {
+        self.keys.iter().map(|&(t, _)| t).collect()
+    }
+
+    pub fn at(&self, time: f64) -> T {
+        let n = self.keys.len();
+        assert!(n > 0, "no keyframes");
+
+        if time <= self.keys[0].0 {
+            return self.keys[0].1;
+        }
+        if time >= self.keys[n - 1].0 {
+            return self.keys[n - 1].1;
+        }
+
+        let i = self.keys.iter().rposition(|&(t, _)| t <= time).expect("time before first key");
+        let (t1, v1) = self.keys[i];
+        let (t2, v2) = self.keys[i + 1];
+        let amount = (time - t1) / (t2 - t1);
+
+        match self.interpolation {
+            Interpolation::Linear => v1.lerp(v2, amount),
+            Interpolation::Bezier => {
+                let v0 = if i > 0 { self.keys[i - 1].1 } else { v1 };
+                let v3 = if i + 2 < n { self.keys[i + 2].1 } else { v2 };
+                T::bezier(v0, v1, v2, v3, amount)
+            }
+        }
+    }
+}
+
+impl Keyframes<Vector3<f64>> {
+    /// Whether no component ever passes through zero. Bézier segments stay within the hull
+    /// of their keys and handles, so it is enough that those all have the same sign in every
+    /// component.
+    fn never_zero(&self) -> bool {
+        let same_sign = |values: &[Vector3<f64>]| {
+            (0..3).all(|c| values.iter().all(|v| v[c] > 0.0) || values.iter().all(|v| v[c] < 0.0))
+        };
+        let n = self.keys.len();
+        if n < 2 {
+            return n == 1 && same_sign(&[self.keys[0].1]);
+        }
+
+        (0..n - 1).all(|i| {
+            let (v1, v2) = (self.keys[i].1, self.keys[i + 1].1);
+            match self.interpolation {
+                Interpolation::Linear => same_sign(&[v1, v2]),
+                Interpolation::Bezier => {
+                    let v0 = if i > 0 { self.keys[i - 1].1 } else { v1 };
+                    let v3 = if i + 2 < n { self.keys[i + 2].1 } else { v2 };
+                    same_sign(&[v1, v1 + (v2 - v0) / 6.0, v2 - (v3 - v1) / 6.0, v2])
+                }
+            }
+        })
+    }
+}
+
+/// Keyframed camera position, target and vertical field of view.
+pub struct CameraPath {
+    pub position: Keyframes<Point3<f64>>,
+    pub target: Keyframes<Point3<f64>>,
+    pub vfov: Keyframes<f64>,
+}
+
+impl CameraPath {
+    pub fn fixed(position: Point3<f64>, target: Point3<f64>, vfov: f64) -> CameraPath {
+        CameraPath {
+            position: Keyframes::constant(position),
+            target: Keyframes::constant(target),
+            vfov: Keyframes::constant(vfov),
+        }
+    }
+}
+
+/// Keyframed translation, rotation and scale of an instance.
+pub struct TransformKeyframes {
+    pub translation: Keyframes<Vector3<f64>>,
+    pub rotation: Keyframes<Quaternion<f64>>,
+    pub scale: Keyframes<Vector3<f64>>,
+}
+
+impl TransformKeyframes {
+    /// Times of all the keys of the components, in no particular order.
+    pub fn key_times(&self) -> Vec<f64> {
+        let mut times = self.translation.times();
+        times.extend(self.rotation.times());
+        times.extend(self.scale.times());
+        times
+    }
+
+    /// Whether the transform can be inverted at all times, which needs the scale to stay
+    /// away from zero.
+    pub fn is_invertible(&self) -> bool {
+        self.scale.never_zero()
+    }
+
+    pub fn at(&self, time: f64) -> Transform {
+        Transform::new(self.translation.at(time), self.rotation.at(time), self.scale.at(time))
+    }
+}
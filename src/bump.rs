@@ -0,0 +1,175 @@
+use cgmath::vec3;
+use cgmath::prelude::*;
+use color::Color;
+use hitable::HitRecord;
+use material::{Material, next_material_id};
+use medium::Medium;
+use ray::Ray;
+use spectrum::{SampledWavelengths, Spectrum};
+use std::sync::Arc;
+use texture::{ScalarTexture, Texture};
+
+/// Distance in world units over which bump maps are differentiated.
+const BUMP_DELTA: f64 = 1e-3;
+
+enum Bump {
+    Height { height: Box<ScalarTexture>, scale: f64 },
+    NormalMap(Box<Texture>),
+}
+
+/// Wraps any material to add detail to its surface by changing the shading normal, either
+/// from a height texture or from a tangent space normal map. Only the shading normal changes,
+/// the geometric one still decides which side of the surface a ray is on.
+pub struct Bumped {
+    material: Arc<Material>,
+    bump: Bump,
+    id: usize,
+}
+
+impl Bumped {
+    /// Bumps the surface up along its normal by `scale` times the value of `height`.
+    pub fn height(material: Arc<Material>, height: Box<ScalarTexture>, scale: f64) -> Bumped {
+        Bumped { material, bump: Bump::Height { height, scale }, id: next_material_id() }
+    }
+
+    /// Normal map whose red, green and blue channels hold the normal along the tangent,
+    /// the bitangent and the surface normal, mapped from `[-1, 1]` to `[0, 1]`. Open images for
+    /// it with `ImageTexture::open_data`.
+    pub fn normal_map(material: Arc<Material>, map: Box<Texture>) -> Bumped {
+        Bumped { material, bump: Bump::NormalMap(map), id: next_material_id() }
+    }
+
+    /// Hit record with the perturbed shading normal. Surfaces without derivatives are left
+    /// alone.
+    fn shade(&self, rec: &HitRecord) -> HitRecord {
+        let n = rec.normal;
+        if rec.dpdu.magnitude2() == 0.0 || rec.dpdv.magnitude2() == 0.0 {
+            return rec.clone();
+        }
+
+        let normal = match self.bump {
+            Bump::Height { ref height, scale } => {
+                let du = BUMP_DELTA / rec.dpdu.magnitude();
+                let dv = BUMP_DELTA / rec.dpdv.magnitude();
+                let h = height.value(rec.u, rec.v, &rec.p);
+                let hu = height.value(rec.u + du, rec.v, &(rec.p + du * rec.dpdu));
+                let hv = height.value(rec.u, rec.v + dv, &(rec.p + dv * rec.dpdv));
+
+                // Derivatives of the displaced surface, without the small terms from the
+                // curvature of the normal.
+                let dpdu = rec.dpdu + scale * (hu - h) / du * n;
+                let dpdv = rec.dpdv + scale * (hv - h) / dv * n;
+                let bumped = dpdu.cross(dpdv).normalize();
+                if bumped.dot(n) < 0.0 { -bumped } else { bumped }
+            }
+            Bump::NormalMap(ref map) => {
+                let c = map.value(rec.u, rec.v, &rec.p);
+                let local = vec3(2.0 * c.r - 1.0, 2.0 * c.g - 1.0, 2.0 * c.b - 1.0);
+                let tangent = (rec.dpdu - n * n.dot(rec.dpdu)).normalize();
+                let bitangent = n.cross(tangent);
+                let bitangent = if bitangent.dot(rec.dpdv) < 0.0 { -bitangent } else { bitangent };
+                (local.x * tangent + local.y * bitangent + local.z * n).normalize()
+            }
+        };
+
+        // Normals turned past the actual surface would shade it from the wrong side.
+        let finite = normal.x.is_finite() && normal.y.is_finite() && normal.z.is_finite();
+        if finite && normal.dot(rec.geometric_normal) > 0.0 {
+            rec.clone().with_shading_normal(normal)
+        } else {
+            rec.clone()
+        }
+    }
+}
+
+impl Material for Bumped {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        let shaded = self.shade(rec);
+        let (scattered, attenuation) = self.material.scatter(r_in, &shaded)?;
+        if shaded.leaks(scattered.direction) {
+            return None;
+        }
+        Some((scattered, attenuation))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.material.albedo(rec)
+    }
+
+    fn interior(&self) -> Option<&Medium> {
+        self.material.interior()
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.material.emitted(rec)
+    }
+
+    fn emitted_spectrum(&self, rec: &HitRecord, wavelengths: &SampledWavelengths) -> Spectrum {
+        self.material.emitted_spectrum(rec, wavelengths)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.material.is_dispersive()
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Point3, Vector3};
+    use material::Lambertian;
+    use std::f64::consts::PI;
+
+    /// Height rising steeply along `x`.
+    struct Ramp(f64);
+
+    impl ScalarTexture for Ramp {
+        fn value(&self, _u: f64, _v: f64, p: &Point3<f64>) -> f64 {
+            self.0 * (3.0 * p.x).sin()
+        }
+    }
+
+    /// Hit on the `xy` plane from above, with the given shading normal.
+    fn hit(normal: Vector3<f64>, x: f64) -> HitRecord {
+        let material: Arc<Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let ray = Ray::new(Point3::new(x, 0.0, 1.0), vec3(0.0, 0.0, -1.0), 0.0);
+        HitRecord::new(&ray, 1.0, vec3(0.0, 0.0, 1.0), (x, 0.0), &material, 0, 0)
+            .with_derivatives(vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0))
+            .with_shading_normal(normal.normalize())
+    }
+
+    #[test]
+    fn flat_height_keeps_the_normal() {
+        let bumped = Bumped::height(Arc::new(Lambertian::new(Box::new(Color::white()))), Box::new(0.5), 1.0);
+        let rec = hit(vec3(0.0, 0.0, 1.0), 0.3);
+        assert!((bumped.shade(&rec).normal - rec.normal).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn bumped_normals_stay_on_the_geometric_side() {
+        let material: Arc<Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        for &scale in &[0.01, 1.0, 100.0, -100.0] {
+            let bumped = Bumped::height(material.clone(), Box::new(Ramp(1.0)), scale);
+            for i in 0..64 {
+                let x = i as f64 / 64.0 * 2.0 * PI;
+                // Interpolated shading normals lean away from the geometric one.
+                for &normal in &[vec3(0.0, 0.0, 1.0), vec3(2.0, 0.0, 1.0), vec3(-3.0, 1.0, 0.5)] {
+                    let shaded = bumped.shade(&hit(normal, x));
+                    assert!(shaded.normal.dot(shaded.geometric_normal) > 0.0, "normal {:?} points into the surface", shaded.normal);
+                    assert!((shaded.normal.magnitude() - 1.0).abs() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn flat_normal_map_keeps_the_normal() {
+        let bumped = Bumped::normal_map(Arc::new(Lambertian::new(Box::new(Color::white()))), Box::new(Color::new(0.5, 0.5, 1.0)));
+        let rec = hit(vec3(0.0, 0.0, 1.0), 0.3);
+        assert!((bumped.shade(&rec).normal - rec.normal).magnitude() < 1e-9);
+    }
+}
@@ -1,22 +1,196 @@
-use cgmath::{Point3, vec3, Vector3};
+use cgmath::{Point3, vec2, vec3, Vector2, Vector3};
 use cgmath::prelude::*;
+use hitable::Hitable;
+use lens::{ApertureShape, LensDistortion};
 use rand::random;
 use ray::Ray;
 use std::f64::consts::PI;
+use std::sync::Arc;
 
-pub struct Camera {
-    lower_left: Vector3<f64>,
-    horizontal: Vector3<f64>,
-    vertical: Vector3<f64>,
-    origin: Point3<f64>,
+/// Maps film coordinates to rays. `s` runs from left to right and `t` from bottom to top,
+/// both in `[0, 1]`. Returns `None` for parts of the film the projection does not cover.
+pub trait Camera : Sync + Send {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray>;
+
+    /// Depth of a point for the depth pass: the distance along the viewing direction for
+    /// cameras with one, as denoisers and compositing expect, and the distance from the
+    /// center for panoramas.
+    fn depth(&self, p: Point3<f64>) -> f64;
+}
+
+/// Film coordinates of a point given in pixels of an `nx` by `ny` image, whose rows run from
+/// the top down while film coordinates run from the bottom up.
+pub fn film_coordinates(x: f64, y: f64, nx: u32, ny: u32) -> (f64, f64) {
+    (x / nx as f64, 1.0 - y / ny as f64)
+}
+
+/// Orthonormal basis of a camera looking from `origin` towards `-w`.
+pub struct Frame {
+    pub origin: Point3<f64>,
+    pub u: Vector3<f64>,
+    pub v: Vector3<f64>,
+    pub w: Vector3<f64>,
+}
+
+impl Frame {
+    pub fn look_at(lookfrom: Point3<f64>, lookat: Point3<f64>, vup: Vector3<f64>) -> Frame {
+        let w = (lookfrom - lookat).normalize();
+        let u = vup.cross(w).normalize();
+        let v = w.cross(u);
+        Frame { origin: lookfrom, u, v, w }
+    }
+
+    /// Converts a direction given in camera space (x right, y up, z backwards) to world space.
+    pub fn to_world(&self, d: Vector3<f64>) -> Vector3<f64> {
+        d.x * self.u + d.y * self.v + d.z * self.w
+    }
+
+    /// Distance of a point in front of the camera along the viewing direction.
+    pub fn linear_depth(&self, p: Point3<f64>) -> f64 {
+        (p - self.origin).dot(-self.w)
+    }
+
+    /// Distance of a point from the origin of the camera.
+    pub fn distance(&self, p: Point3<f64>) -> f64 {
+        (p - self.origin).magnitude()
+    }
+}
+
+/// How far the shutter is open over the course of the exposure.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShutterCurve {
+    /// Opens and closes instantly.
+    Box,
+    /// Opens linearly during the first `open` fraction of the interval and closes linearly
+    /// during the last `close` fraction.
+    Trapezoid { open: f64, close: f64 },
+    /// Openness sampled at evenly spaced times from the start to the end of the interval.
+    Custom(Vec<f64>),
+}
+
+impl ShutterCurve {
+    fn openness(&self, x: f64) -> f64 {
+        match *self {
+            ShutterCurve::Box => 1.0,
+            ShutterCurve::Trapezoid { open, close } => {
+                if x < open {
+                    x / open
+                } else if x > 1.0 - close {
+                    (1.0 - x) / close
+                } else {
+                    1.0
+                }
+            }
+            ShutterCurve::Custom(ref values) => {
+                if values.len() < 2 {
+                    return values.first().cloned().unwrap_or(1.0);
+                }
+                let position = x * (values.len() - 1) as f64;
+                let i = (position as usize).min(values.len() - 2);
+                let f = position - i as f64;
+                values[i] * (1.0 - f) + values[i + 1] * f
+            }
+        }
+    }
+}
+
+/// Number of segments the shutter curve is tabulated into for sampling.
+const SHUTTER_CURVE_STEPS: usize = 256;
+
+/// Time interval during which the shutter is open. Every camera samples ray times from it,
+/// which is what produces motion blur.
+///
+/// With a rolling shutter the rows are exposed one after another, the top row first: the
+/// bottom row opens and closes `rolling` time units after the top row.
+#[derive(Clone)]
+pub struct Shutter {
+    pub time0: f64,
+    pub time1: f64,
+    pub rolling: f64,
+    /// Cumulative distribution of the shutter curve, empty for a box curve.
+    cdf: Arc<Vec<f64>>,
+}
+
+impl Shutter {
+    pub fn new(time0: f64, time1: f64) -> Shutter {
+        Shutter { time0, time1, rolling: 0.0, cdf: Arc::new(Vec::new()) }
+    }
+
+    pub fn with_curve(mut self, curve: &ShutterCurve) -> Shutter {
+        if *curve == ShutterCurve::Box {
+            self.cdf = Arc::new(Vec::new());
+            return self;
+        }
+
+        let mut cdf = Vec::with_capacity(SHUTTER_CURVE_STEPS + 1);
+        let mut total = 0.0;
+        cdf.push(0.0);
+        for i in 0..SHUTTER_CURVE_STEPS {
+            let x0 = i as f64 / SHUTTER_CURVE_STEPS as f64;
+            let x1 = (i + 1) as f64 / SHUTTER_CURVE_STEPS as f64;
+            total += 0.5 * (curve.openness(x0).max(0.0) + curve.openness(x1).max(0.0));
+            cdf.push(total);
+        }
+        assert!(total > 0.0, "shutter never opens");
+        for value in &mut cdf {
+            *value /= total;
+        }
+
+        self.cdf = Arc::new(cdf);
+        self
+    }
+
+    pub fn with_rolling(mut self, rolling: f64) -> Shutter {
+        assert!(rolling >= 0.0, "rolling shutter readout must not be negative");
+        self.rolling = rolling;
+        self
+    }
+
+    /// Time when the shutter of the last row closes.
+    pub fn closing_time(&self) -> f64 {
+        self.time1 + self.rolling
+    }
+
+    /// Samples the time of a ray through the row at film coordinate `t`.
+    pub fn sample_time(&self, t: f64) -> f64 {
+        let row_offset = (1.0 - t) * self.rolling;
+        self.time0 + row_offset + self.sample_fraction() * (self.time1 - self.time0)
+    }
+
+    /// Fraction of the exposure at which to sample, distributed according to the curve.
+    fn sample_fraction(&self) -> f64 {
+        let u = random::<f64>();
+        if self.cdf.is_empty() {
+            return u;
+        }
+
+        let i = match self.cdf.binary_search_by(|c| c.partial_cmp(&u).unwrap()) {
+            Ok(i) => i.min(SHUTTER_CURVE_STEPS - 1),
+            Err(i) => i.max(1) - 1,
+        };
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let f = if width > 0.0 { (u - self.cdf[i]) / width } else { 0.0 };
+        (i as f64 + f) / SHUTTER_CURVE_STEPS as f64
+    }
+}
+
+/// Thin lens camera. Besides the focal distance and aperture size, the aperture shape,
+/// mechanical vignetting, tilt-shift movements and lens distortion can be configured.
+pub struct PerspectiveCamera {
+    frame: Frame,
+    half_width: f64,
+    half_height: f64,
+    focus_dist: f64,
     lens_radius: f64,
-    u: Vector3<f64>,
-    v: Vector3<f64>,
-    time0: f64,
-    time1: f64,
+    aperture_shape: ApertureShape,
+    cat_eye: f64,
+    focal_plane_normal: Vector3<f64>,
+    shift: Vector2<f64>,
+    distortion: LensDistortion,
+    shutter: Shutter,
 }
 
-impl Camera {
+impl PerspectiveCamera {
     pub fn new(lookfrom: Point3<f64>,
                lookat: Point3<f64>,
                vup: Vector3<f64>,
@@ -24,44 +198,202 @@ impl Camera {
                aspect: f64,
                aperture: f64,
                focus_dist: f64,
-               time0: f64,
-               time1: f64) -> Camera {
+               shutter: Shutter) -> PerspectiveCamera {
         let theta = vfov * PI / 180.0;
         let half_height = (theta / 2.0).tan();
         let half_width = aspect * half_height;
-        let w = (lookfrom - lookat).normalize();
-        let u = vup.cross(w).normalize();
-        let v = w.cross(u);
-        Camera {
-            lower_left: lookfrom.to_vec() - half_width * focus_dist * u - half_height * focus_dist * v - focus_dist * w,
-            horizontal: 2.0 * half_width * focus_dist * u,
-            vertical: 2.0 * half_height * focus_dist * v,
-            origin: lookfrom,
+        PerspectiveCamera {
+            frame: Frame::look_at(lookfrom, lookat, vup),
+            half_width,
+            half_height,
+            focus_dist,
             lens_radius: aperture / 2.0,
-            u,
-            v,
-            time0,
-            time1,
+            aperture_shape: ApertureShape::Circular,
+            cat_eye: 0.0,
+            focal_plane_normal: vec3(0.0, 0.0, 1.0),
+            shift: vec2(0.0, 0.0),
+            distortion: LensDistortion::default(),
+            shutter,
+        }
+    }
+
+    pub fn with_aperture_shape(mut self, shape: ApertureShape) -> PerspectiveCamera {
+        self.aperture_shape = shape;
+        self
+    }
+
+    /// Simulates mechanical vignetting: towards the edges of the film the lens barrel clips
+    /// the aperture into a cat's eye shape. `amount` is how far the clipping circle moves per
+    /// unit of distance from the center of the film, relative to the aperture radius.
+    pub fn with_cat_eye(mut self, amount: f64) -> PerspectiveCamera {
+        self.cat_eye = amount;
+        self
+    }
+
+    /// Tilts the plane of focus by `tilt` degrees around the horizontal axis and `swing`
+    /// degrees around the vertical axis, as a tilt-shift lens does.
+    pub fn with_tilt(mut self, tilt: f64, swing: f64) -> PerspectiveCamera {
+        let (tilt, swing) = (tilt.to_radians(), swing.to_radians());
+        self.focal_plane_normal = vec3(swing.sin() * tilt.cos(), -tilt.sin(), swing.cos() * tilt.cos());
+        self
+    }
+
+    /// Shifts the film parallel to the lens, in fractions of the film width and height.
+    pub fn with_shift(mut self, x: f64, y: f64) -> PerspectiveCamera {
+        self.shift = vec2(x, y);
+        self
+    }
+
+    pub fn with_distortion(mut self, distortion: LensDistortion) -> PerspectiveCamera {
+        self.distortion = distortion;
+        self
+    }
+
+    /// Focuses on whatever is seen through the center of the lens at film coordinates `s`,
+    /// `t`. Keeps the current focus distance if nothing is hit.
+    pub fn autofocus<T: Hitable + ?Sized>(mut self, world: &T, s: f64, t: f64) -> PerspectiveCamera {
+        if let Some(film) = self.film_point(s, t) {
+            let direction = self.frame.to_world(vec3(film.x, film.y, -1.0));
+            let ray = Ray::new(self.frame.origin, direction, self.shutter.time0);
+            if let Some(rec) = world.hit(&ray, 0.001, f64::max_value()) {
+                self.focus_dist = (rec.p - self.frame.origin).dot(-self.frame.w);
+            }
         }
+        self
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * random_in_unit_disc();
-        let offset = self.u * rd.x + self.v * rd.y;
-        let time = self.time0 + random::<f64>() * (self.time1 - self.time0);
-        Ray::new(
-            self.origin + offset,
-            self.lower_left + s * self.horizontal + t * self.vertical - self.origin.to_vec() - offset,
-            time,
-        )
+    /// Point on the film plane at unit distance in front of the lens, in camera space.
+    fn film_point(&self, s: f64, t: f64) -> Option<Vector2<f64>> {
+        let film = vec2((2.0 * (s + self.shift.x) - 1.0) * self.half_width, (2.0 * (t + self.shift.y) - 1.0) * self.half_height);
+        if self.distortion.is_identity() {
+            Some(film)
+        } else {
+            self.distortion.undistort(film / self.half_height).map(|p| self.half_height * p)
+        }
     }
 }
 
-fn random_in_unit_disc() -> Vector3<f64> {
-    loop {
-        let v = 2.0 * vec3(random::<f64>(), random::<f64>(), random::<f64>()) - vec3(1.0, 1.0, 0.0);
-        if v.dot(v) >= 1.0 {
-            return v;
+impl Camera for PerspectiveCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let film = self.film_point(s, t)?;
+
+        let lens = self.aperture_shape.sample();
+        if self.cat_eye > 0.0 {
+            let barrel = self.cat_eye * film / self.half_height;
+            if (lens - barrel).magnitude2() > 1.0 {
+                return None;
+            }
+        }
+
+        // Ray through the center of the lens, intersected with the (possibly tilted) plane of focus.
+        // With a strong tilt, rays towards the edge of the film may run parallel to the plane
+        // of focus or only meet it behind the lens, and never come into focus.
+        let direction = vec3(film.x, film.y, -1.0);
+        let distance = -self.focus_dist * self.focal_plane_normal.z / direction.dot(self.focal_plane_normal);
+        if !distance.is_finite() || distance <= 0.0 {
+            return None;
+        }
+        let focus = distance * direction;
+
+        let offset = self.lens_radius * vec3(lens.x, lens.y, 0.0);
+        Some(Ray::new(
+            self.frame.origin + self.frame.to_world(offset),
+            self.frame.to_world(focus - offset),
+            self.shutter.sample_time(t),
+        ))
+    }
+
+    fn depth(&self, p: Point3<f64>) -> f64 {
+        self.frame.linear_depth(p)
+    }
+}
+
+/// Parallel projection where every ray travels in the viewing direction. `height` is the
+/// extent of the film in world units.
+pub struct OrthographicCamera {
+    frame: Frame,
+    half_width: f64,
+    half_height: f64,
+    shutter: Shutter,
+}
+
+impl OrthographicCamera {
+    pub fn new(lookfrom: Point3<f64>, lookat: Point3<f64>, vup: Vector3<f64>, height: f64, aspect: f64, shutter: Shutter) -> OrthographicCamera {
+        OrthographicCamera {
+            frame: Frame::look_at(lookfrom, lookat, vup),
+            half_width: aspect * height / 2.0,
+            half_height: height / 2.0,
+            shutter,
+        }
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let x = (2.0 * s - 1.0) * self.half_width;
+        let y = (2.0 * t - 1.0) * self.half_height;
+        Some(Ray::new(
+            self.frame.origin + x * self.frame.u + y * self.frame.v,
+            -self.frame.w,
+            self.shutter.sample_time(t),
+        ))
+    }
+
+    fn depth(&self, p: Point3<f64>) -> f64 {
+        self.frame.linear_depth(p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Camera at the origin looking down `-z`, so that camera space is world space, with a
+    /// wide aperture that blurs everything off the plane of focus.
+    fn tilted_camera(tilt: f64, swing: f64) -> PerspectiveCamera {
+        PerspectiveCamera::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0), vec3(0.0, 1.0, 0.0), 90.0, 1.5, 0.5, 4.0, Shutter::new(0.0, 1.0))
+            .with_tilt(tilt, swing)
+    }
+
+    /// Where a ray from the lens meets the plane of focus. Rays leave the lens at
+    /// `origin` and are aimed at their focus point, which is `origin + direction`.
+    fn focus(ray: &Ray) -> Point3<f64> {
+        ray.origin + ray.direction
+    }
+
+    #[test]
+    fn tilted_plane_of_focus_is_sharp() {
+        let camera = tilted_camera(20.0, -10.0);
+        let on_axis = Point3::new(0.0, 0.0, -4.0);
+        for &(s, t) in &[(0.5, 0.5), (0.1, 0.2), (0.9, 0.8), (0.3, 0.95)] {
+            let first = focus(&camera.get_ray(s, t).expect("no ray through the film"));
+            assert!((first - on_axis).dot(camera.focal_plane_normal).abs() < 1e-9, "focus {:?} is off the plane", first);
+            assert!(first.z < 0.0, "focus {:?} is behind the lens", first);
+            // Rays through other points of the lens converge on the same point.
+            for _ in 0..100 {
+                let ray = camera.get_ray(s, t).expect("no ray through the film");
+                assert!((focus(&ray) - first).magnitude() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn strongly_tilted_plane_of_focus_is_never_behind_the_lens() {
+        // Tilted by 70 degrees, the plane of focus is only in front of the lens for the lower
+        // part of the film.
+        let camera = tilted_camera(70.0, 0.0);
+        let (mut traced, mut skipped) = (0, 0);
+        for i in 0..50 {
+            let t = (i as f64 + 0.5) / 50.0;
+            match camera.get_ray(0.5, t) {
+                Some(ray) => {
+                    assert!(focus(&ray).z < 0.0, "focus {:?} at t = {} is behind the lens", focus(&ray), t);
+                    assert!(ray.direction.z < 0.0, "ray at t = {} is traced backwards", t);
+                    traced += 1;
+                }
+                None => skipped += 1,
+            }
         }
+        assert!(traced > 0 && skipped > 0, "{} rays traced, {} skipped", traced, skipped);
     }
 }
@@ -23,6 +23,10 @@ impl Color {
     pub fn gamma_correct(&self) -> Color {
         Color::new(self.r.sqrt(), self.g.sqrt(), self.b.sqrt())
     }
+
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
 }
 
 impl ops::Mul<Color> for f64 {
@@ -63,4 +67,20 @@ impl ops::DivAssign<f64> for Color {
         self.g /= value;
         self.b /= value;
     }
-}
\ No newline at end of file
+}
+
+impl ops::Mul<f64> for Color {
+    type Output = Color;
+
+    fn mul(self, rhs: f64) -> Color {
+        Color::new(self.r * rhs, self.g * rhs, self.b * rhs)
+    }
+}
+
+impl ops::Div<f64> for Color {
+    type Output = Color;
+
+    fn div(self, rhs: f64) -> Color {
+        Color::new(self.r / rhs, self.g / rhs, self.b / rhs)
+    }
+}
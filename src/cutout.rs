@@ -0,0 +1,94 @@
+use aabb::AABB;
+use hitable::{Hitable, HitRecord};
+use rand::random;
+use ray::Ray;
+use texture::ScalarTexture;
+
+/// Cuts holes into any hitable with an opacity texture, so that a leaf or a fence can be a
+/// single quad. Rays pass through where the opacity is zero and are stopped where it is one;
+/// in between they pass through at random, in proportion to the transparency. As every ray
+/// goes through here, including the ones finding light for the surfaces behind, shadows get
+/// the same holes.
+pub struct Cutout {
+    hitable: Box<Hitable>,
+    opacity: Box<ScalarTexture>,
+}
+
+impl Cutout {
+    pub fn new(hitable: Box<Hitable>, opacity: Box<ScalarTexture>) -> Cutout {
+        Cutout { hitable, opacity }
+    }
+}
+
+impl Hitable for Cutout {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut t_min = t_min;
+        loop {
+            let rec = self.hitable.hit(ray, t_min, t_max)?;
+            let opacity = self.opacity.value(rec.u, rec.v, &rec.p);
+            if opacity >= 1.0 || (opacity > 0.0 && random::<f64>() < opacity) {
+                return Some(rec);
+            }
+            t_min = rec.t;
+        }
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        self.hitable.bounding_box(t0, t1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Point3, vec3};
+    use color::Color;
+    use material::{Lambertian, Material};
+    use quad::Quad;
+    use std::sync::Arc;
+
+    /// Unit quad in the `xy` plane with the given opacity, in front of an opaque one at
+    /// `z = -1`.
+    fn scene(opacity: f64) -> (Cutout, Quad) {
+        let material: Arc<Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let quad = |z: f64| Quad::new(Point3::new(0.0, 0.0, z), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), material.clone());
+        (Cutout::new(Box::new(quad(0.0)), Box::new(opacity)), quad(-1.0))
+    }
+
+    fn ray() -> Ray {
+        Ray::new(Point3::new(0.5, 0.5, 1.0), vec3(0.0, 0.0, -1.0), 0.0)
+    }
+
+    #[test]
+    fn transparent_surfaces_are_never_hit() {
+        let (cutout, _) = scene(0.0);
+        for _ in 0..1000 {
+            assert!(cutout.hit(&ray(), 0.001, f64::MAX).is_none());
+        }
+    }
+
+    #[test]
+    fn opaque_surfaces_are_always_hit() {
+        let (cutout, _) = scene(1.0);
+        for _ in 0..1000 {
+            let rec = cutout.hit(&ray(), 0.001, f64::MAX).expect("opaque cutout let a ray through");
+            assert!((rec.t - 1.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn fractional_opacity_stops_that_fraction_of_rays() {
+        const RAYS: usize = 100_000;
+        let (cutout, behind) = scene(0.3);
+        let mut hits = 0;
+        for _ in 0..RAYS {
+            match cutout.hit(&ray(), 0.001, f64::MAX) {
+                Some(_) => hits += 1,
+                None => assert!(behind.hit(&ray(), 0.001, f64::MAX).is_some()),
+            }
+        }
+        // Five standard deviations of the binomial distribution.
+        let tolerance = 5.0 * (0.3 * 0.7 / RAYS as f64).sqrt();
+        assert!((hits as f64 / RAYS as f64 - 0.3).abs() < tolerance, "{} of {} rays were stopped", hits, RAYS);
+    }
+}
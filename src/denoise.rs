@@ -0,0 +1,109 @@
+use color::Color;
+use film::{Channel, Film};
+
+/// Feature buffers a film needs to have for `denoise` to work.
+pub const FEATURE_CHANNELS: [Channel; 4] = [Channel::Albedo, Channel::Normal, Channel::Depth, Channel::Variance];
+
+const ITERATIONS: u32 = 5;
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+const SIGMA_LUMINANCE: f64 = 4.0;
+const SIGMA_NORMAL: f64 = 64.0;
+const SIGMA_DEPTH: f64 = 0.02;
+const EPSILON: f64 = 1e-4;
+
+/// Replaces the beauty pass of the film with a denoised version using an edge-avoiding
+/// à-trous wavelet filter (Dammertz et al. 2010).
+///
+/// The albedo is divided out before filtering so that texture detail is not blurred, and
+/// neighbours are weighted by how similar their normal and depth are and by how much their
+/// luminance differs relative to the estimated per-pixel variance.
+pub fn denoise(film: &mut Film) {
+    assert!(FEATURE_CHANNELS.iter().all(|&channel| film.has(channel)), "denoising needs the feature channels");
+    let width = film.width as i64;
+    let height = film.height as i64;
+
+    let albedo = film.layer(Channel::Albedo).to_vec();
+    let normals = film.layer(Channel::Normal).to_vec();
+    let depths: Vec<f64> = film.layer(Channel::Depth).iter().map(|c| c.r).collect();
+
+    let mut color: Vec<Color> = film.layer(Channel::Beauty).iter().zip(&albedo)
+        .map(|(c, a)| demodulate(*c, *a))
+        .collect();
+    let mut variance: Vec<f64> = film.layer(Channel::Variance).iter().zip(&albedo)
+        .map(|(v, a)| v.r / a.luminance().max(EPSILON).powi(2))
+        .collect();
+
+    for iteration in 0..ITERATIONS {
+        let step = 1 << iteration;
+        let mut next_color = color.clone();
+        let mut next_variance = variance.clone();
+
+        for y in 0..height {
+            for x in 0..width {
+                let p = (y * width + x) as usize;
+                if is_background(&normals[p]) {
+                    continue;
+                }
+
+                let luminance_p = color[p].luminance();
+                let luminance_sigma = SIGMA_LUMINANCE * variance[p].sqrt() + EPSILON;
+
+                let mut sum = Color::black();
+                let mut sum_variance = 0.0;
+                let mut total_weight = 0.0;
+
+                for (dy, ky) in KERNEL.iter().enumerate() {
+                    for (dx, kx) in KERNEL.iter().enumerate() {
+                        let qx = x + (dx as i64 - 2) * step;
+                        let qy = y + (dy as i64 - 2) * step;
+                        if qx < 0 || qy < 0 || qx >= width || qy >= height {
+                            continue;
+                        }
+
+                        let q = (qy * width + qx) as usize;
+                        if is_background(&normals[q]) {
+                            continue;
+                        }
+
+                        let normal_weight = dot(&normals[p], &normals[q]).max(0.0).powf(SIGMA_NORMAL);
+                        let depth_weight = (-(depths[p] - depths[q]).abs() / (SIGMA_DEPTH * depths[p] * step as f64 + EPSILON)).exp();
+                        let luminance_weight = (-(luminance_p - color[q].luminance()).abs() / luminance_sigma).exp();
+
+                        let weight = kx * ky * normal_weight * depth_weight * luminance_weight;
+                        sum += weight * color[q];
+                        sum_variance += weight * weight * variance[q];
+                        total_weight += weight;
+                    }
+                }
+
+                if total_weight > 0.0 {
+                    next_color[p] = sum / total_weight;
+                    next_variance[p] = sum_variance / (total_weight * total_weight);
+                }
+            }
+        }
+
+        color = next_color;
+        variance = next_variance;
+    }
+
+    let result = color.iter().zip(&albedo).map(|(c, a)| remodulate(*c, *a)).collect();
+    film.set_layer(Channel::Beauty, result);
+}
+
+fn demodulate(color: Color, albedo: Color) -> Color {
+    Color::new(color.r / albedo.r.max(EPSILON), color.g / albedo.g.max(EPSILON), color.b / albedo.b.max(EPSILON))
+}
+
+fn remodulate(color: Color, albedo: Color) -> Color {
+    Color::new(color.r * albedo.r.max(EPSILON), color.g * albedo.g.max(EPSILON), color.b * albedo.b.max(EPSILON))
+}
+
+fn dot(a: &Color, b: &Color) -> f64 {
+    a.r * b.r + a.g * b.g + a.b * b.b
+}
+
+fn is_background(normal: &Color) -> bool {
+    dot(normal, normal) == 0.0
+}
@@ -0,0 +1,178 @@
+use color::Color;
+use image::{ImageBuffer, Rgb};
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+/// A named layer of the film. `Beauty` is always present, the rest are arbitrary output
+/// variables written from the first hit of each camera ray.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Beauty,
+    Albedo,
+    Normal,
+    Depth,
+    Position,
+    ObjectId,
+    MaterialId,
+    Direct,
+    Indirect,
+    Variance,
+}
+
+pub const AOV_CHANNELS: [Channel; 9] = [
+    Channel::Albedo,
+    Channel::Normal,
+    Channel::Depth,
+    Channel::Position,
+    Channel::ObjectId,
+    Channel::MaterialId,
+    Channel::Direct,
+    Channel::Indirect,
+    Channel::Variance,
+];
+
+impl Channel {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Channel::Beauty => "beauty",
+            Channel::Albedo => "albedo",
+            Channel::Normal => "normal",
+            Channel::Depth => "depth",
+            Channel::Position => "position",
+            Channel::ObjectId => "object_id",
+            Channel::MaterialId => "material_id",
+            Channel::Direct => "direct",
+            Channel::Indirect => "indirect",
+            Channel::Variance => "variance",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Channel> {
+        AOV_CHANNELS.iter().cloned().chain(Some(Channel::Beauty)).find(|c| c.name() == name)
+    }
+}
+
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    channels: Vec<Channel>,
+    layers: Vec<Vec<Color>>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32, aovs: &[Channel]) -> Film {
+        let mut channels = vec![Channel::Beauty];
+        for &channel in aovs {
+            if !channels.contains(&channel) {
+                channels.push(channel);
+            }
+        }
+
+        let layers = channels.iter().map(|_| vec![Color::black(); (width * height) as usize]).collect();
+        Film { width, height, channels, layers }
+    }
+
+    #[allow(dead_code)]
+    pub fn channels(&self) -> &[Channel] {
+        &self.channels
+    }
+
+    pub fn has(&self, channel: Channel) -> bool {
+        self.channels.contains(&channel)
+    }
+
+    pub fn get(&self, channel: Channel, x: u32, y: u32) -> Color {
+        self.layer(channel)[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, channel: Channel, x: u32, y: u32, value: Color) {
+        let index = (y * self.width + x) as usize;
+        if let Some(layer) = self.layer_mut(channel) {
+            layer[index] = value;
+        }
+    }
+
+    pub fn set_layer(&mut self, channel: Channel, values: Vec<Color>) {
+        assert_eq!(values.len(), (self.width * self.height) as usize, "layer size does not match film");
+        if let Some(layer) = self.layer_mut(channel) {
+            *layer = values;
+        }
+    }
+
+    /// Scales the channels that hold radiance by the exposure of the camera.
+    pub fn expose(&mut self, exposure: f64) {
+        for &channel in &[Channel::Beauty, Channel::Direct, Channel::Indirect] {
+            if let Some(layer) = self.layer_mut(channel) {
+                for value in layer.iter_mut() {
+                    *value = exposure * *value;
+                }
+            }
+        }
+
+        if let Some(layer) = self.layer_mut(Channel::Variance) {
+            for value in layer.iter_mut() {
+                *value = exposure * exposure * *value;
+            }
+        }
+    }
+
+    pub fn layer(&self, channel: Channel) -> &[Color] {
+        let index = self.channels.iter().position(|&c| c == channel).expect("channel not in film");
+        &self.layers[index]
+    }
+
+    fn layer_mut(&mut self, channel: Channel) -> Option<&mut Vec<Color>> {
+        match self.channels.iter().position(|&c| c == channel) {
+            Some(index) => Some(&mut self.layers[index]),
+            None => None,
+        }
+    }
+
+    /// Saves the beauty pass as a gamma corrected PNG to `path` and the given channels as
+    /// linear float PFMs next to it, named `<stem>.<channel>.pfm`.
+    pub fn save(&self, path: &Path, aovs: &[Channel]) -> io::Result<()> {
+        self.save_png(Channel::Beauty, path)?;
+
+        for &channel in aovs {
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+            let aov_path = path.with_file_name(format!("{}.{}.pfm", stem, channel.name()));
+            self.save_pfm(channel, &aov_path)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn save_png(&self, channel: Channel, path: &Path) -> io::Result<()> {
+        let img = ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            let col = self.get(channel, x, y).gamma_correct();
+
+            let ir = (255.99 * col.r) as u8;
+            let ig = (255.99 * col.g) as u8;
+            let ib = (255.99 * col.b) as u8;
+
+            Rgb([ir, ig, ib])
+        });
+
+        img.save(path)
+    }
+
+    /// Writes the channel without any tone mapping as a Portable Float Map, which stores
+    /// rows bottom to top in little endian 32-bit floats.
+    pub fn save_pfm(&self, channel: Channel, path: &Path) -> io::Result<()> {
+        let mut out = io::BufWriter::new(File::create(path)?);
+        write!(out, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let col = self.get(channel, x, y);
+                for value in &[col.r, col.g, col.b] {
+                    out.write_all(&(*value as f32).to_le_bytes())?;
+                }
+            }
+        }
+
+        out.flush()
+    }
+}
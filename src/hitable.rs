@@ -1,14 +1,31 @@
 use aabb::AABB;
 use cgmath::{Point3, Vector3};
+use cgmath::prelude::*;
 use material::Material;
 use ray::Ray;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+#[derive(Clone)]
 pub struct HitRecord {
     pub t: f64,
     pub p: Point3<f64>,
+    /// Outward normal used for shading, which may be interpolated or perturbed by a bump map.
     pub normal: Vector3<f64>,
+    /// Outward normal of the actual surface, which decides which side of it a ray is on.
+    pub geometric_normal: Vector3<f64>,
+    /// Whether the ray hit the outside of the surface, against the geometric normal.
+    pub front_face: bool,
+    /// Texture coordinates.
+    pub u: f64,
+    pub v: f64,
+    /// Derivatives of the position with respect to the texture coordinates, spanning the
+    /// tangent plane. Zero where the primitive has no parameterization.
+    pub dpdu: Vector3<f64>,
+    pub dpdv: Vector3<f64>,
     pub material: Arc<Material>,
+    pub object_id: usize,
+    pub material_id: usize,
 }
 
 pub trait Hitable : Sync + Send {
@@ -20,12 +37,58 @@ pub trait Hitable : Sync + Send {
 }
 
 impl HitRecord {
-    pub fn new(t: f64, p: Point3<f64>, normal: Vector3<f64>, material: &Arc<Material>) -> HitRecord {
+    /// Hit of `ray` at distance `t` on a surface with the given outward normal.
+    pub fn new(ray: &Ray, t: f64, normal: Vector3<f64>, (u, v): (f64, f64), material: &Arc<Material>, object_id: usize, material_id: usize) -> HitRecord {
         HitRecord {
             t,
-            p,
+            p: ray.point_at(t),
             normal,
-            material: material.clone()
+            geometric_normal: normal,
+            front_face: ray.direction.dot(normal) < 0.0,
+            u,
+            v,
+            dpdu: Vector3::zero(),
+            dpdv: Vector3::zero(),
+            material: material.clone(),
+            object_id,
+            material_id,
         }
     }
+
+    pub fn with_derivatives(mut self, dpdu: Vector3<f64>, dpdv: Vector3<f64>) -> HitRecord {
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+        self
+    }
+
+    /// Replaces the shading normal, keeping the geometric one.
+    pub fn with_shading_normal(mut self, normal: Vector3<f64>) -> HitRecord {
+        self.normal = normal;
+        self
+    }
+
+    /// The same hit as seen by another ray arriving at the point, such as one bounced back
+    /// onto the surface inside a coat, with the side of the surface taken from that ray.
+    pub fn seen_by(&self, ray: &Ray) -> HitRecord {
+        HitRecord { t: 0.0, front_face: ray.direction.dot(self.geometric_normal) < 0.0, ..self.clone() }
+    }
+
+    /// Shading normal turned towards the side of the surface the ray came from.
+    pub fn facing_normal(&self) -> Vector3<f64> {
+        if self.front_face { self.normal } else { -self.normal }
+    }
+
+    /// Whether a scattered direction is on the other side of the surface than the shading
+    /// normal says. Such rays are dropped, as they would let light leak through bumped or
+    /// smoothly shaded surfaces.
+    pub fn leaks(&self, direction: Vector3<f64>) -> bool {
+        direction.dot(self.normal) * direction.dot(self.geometric_normal) < 0.0
+    }
+}
+
+static NEXT_OBJECT_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// Allocates a new id for the object id pass. Zero is reserved for background.
+pub fn next_object_id() -> usize {
+    NEXT_OBJECT_ID.fetch_add(1, Ordering::Relaxed)
 }
@@ -0,0 +1,142 @@
+use aabb::AABB;
+use animation::TransformKeyframes;
+use cgmath::{Matrix4, Point3, Quaternion, Vector3};
+use cgmath::prelude::*;
+use cgmath::Transform as MatrixTransform;
+use hitable::{Hitable, HitRecord};
+use ray::Ray;
+
+/// Scale, then rotation, then translation.
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    pub translation: Vector3<f64>,
+    pub rotation: Quaternion<f64>,
+    pub scale: Vector3<f64>,
+}
+
+impl Transform {
+    pub fn new(translation: Vector3<f64>, rotation: Quaternion<f64>, scale: Vector3<f64>) -> Transform {
+        Transform { translation, rotation, scale }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f64> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+/// Places a hitable in the world with a transform, so that the same geometry can be moved,
+/// rotated and scaled without touching its definition.
+pub struct Instance {
+    hitable: Box<Hitable>,
+    to_world: Matrix4<f64>,
+    to_object: Matrix4<f64>,
+}
+
+impl Instance {
+    pub fn new(hitable: Box<Hitable>, transform: Transform) -> Instance {
+        let to_world = transform.matrix();
+        let to_object = to_world.invert().expect("transform is not invertible");
+        Instance { hitable, to_world, to_object }
+    }
+}
+
+impl Hitable for Instance {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hit_transformed(&*self.hitable, &self.to_world, &self.to_object, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        self.hitable.bounding_box(t0, t1).map(|bb| transform_box(&self.to_world, &bb))
+    }
+}
+
+/// Instance whose transform changes over time. The transform is interpolated at the time
+/// of each ray, which blurs rotation and scaling as well as translation.
+pub struct AnimatedInstance {
+    hitable: Box<Hitable>,
+    motion: TransformKeyframes,
+}
+
+/// Number of steps the shutter interval is divided into when bounding the motion.
+const MOTION_BOUND_STEPS: u32 = 32;
+
+impl AnimatedInstance {
+    /// Panics if the scale can reach zero, where the object would be flattened and could not
+    /// be intersected.
+    pub fn new(hitable: Box<Hitable>, motion: TransformKeyframes) -> AnimatedInstance {
+        assert!(motion.is_invertible(), "the scale of an animated instance must not reach zero");
+        AnimatedInstance { hitable, motion }
+    }
+}
+
+impl Hitable for AnimatedInstance {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let to_world = self.motion.at(ray.time).matrix();
+        let to_object = to_world.invert()?;
+        hit_transformed(&*self.hitable, &to_world, &to_object, ray, t_min, t_max)
+    }
+
+    /// Unites the boxes of the object at the keys and at evenly spaced times during the
+    /// interval. Between two such times points can still bulge out along their arcs or
+    /// grow with the scale, so the result is padded by how far that can take them.
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        let bb = self.hitable.bounding_box(t0, t1)?;
+        let radius = (0..8).map(|i| box_corner(&bb, i).to_vec().magnitude()).fold(0.0, f64::max);
+
+        let mut times: Vec<f64> = (0..MOTION_BOUND_STEPS + 1)
+            .map(|i| t0 + (t1 - t0) * i as f64 / MOTION_BOUND_STEPS as f64)
+            .chain(self.motion.key_times().into_iter().filter(|&t| t > t0 && t < t1))
+            .collect();
+        times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let transforms: Vec<Transform> = times.iter().map(|&t| self.motion.at(t)).collect();
+        let mut result = transform_box(&transforms[0].matrix(), &bb);
+        let mut padding: f64 = 0.0;
+
+        for pair in transforms.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            result = result.union(&transform_box(&b.matrix(), &bb));
+
+            let max_scale = a.scale.x.abs().max(a.scale.y.abs()).max(a.scale.z.abs())
+                .max(b.scale.x.abs()).max(b.scale.y.abs()).max(b.scale.z.abs());
+            let half_angle = a.rotation.dot(b.rotation).abs().min(1.0).acos();
+            let arc = max_scale * radius * (1.0 - half_angle.cos());
+            let growth = radius * (b.scale - a.scale).magnitude();
+            let drift = (b.translation - a.translation).magnitude() / 2.0;
+
+            padding = padding.max(arc + growth + drift);
+        }
+
+        Some(result.padded(padding))
+    }
+}
+
+fn hit_transformed(hitable: &Hitable, to_world: &Matrix4<f64>, to_object: &Matrix4<f64>, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    let object_ray = Ray::new(to_object.transform_point(ray.origin), to_object.transform_vector(ray.direction), ray.time);
+
+    hitable.hit(&object_ray, t_min, t_max).map(|mut rec| {
+        rec.p = to_world.transform_point(rec.p);
+        rec.normal = to_object.transpose().transform_vector(rec.normal).normalize();
+        rec.geometric_normal = to_object.transpose().transform_vector(rec.geometric_normal).normalize();
+        rec.dpdu = to_world.transform_vector(rec.dpdu);
+        rec.dpdv = to_world.transform_vector(rec.dpdv);
+        rec
+    })
+}
+
+fn box_corner(bb: &AABB, i: usize) -> Point3<f64> {
+    let x = if i & 1 == 0 { bb.min.x } else { bb.max.x };
+    let y = if i & 2 == 0 { bb.min.y } else { bb.max.y };
+    let z = if i & 4 == 0 { bb.min.z } else { bb.max.z };
+    Point3::new(x, y, z)
+}
+
+/// Smallest box containing all corners of `bb` after transforming them.
+pub fn transform_box(matrix: &Matrix4<f64>, bb: &AABB) -> AABB {
+    let corner = |i: usize| matrix.transform_point(box_corner(bb, i));
+
+    let first = corner(0);
+    (1..8).map(corner).fold(AABB::new(first, first), |acc, p| acc.union(&AABB::new(p, p)))
+}
@@ -0,0 +1,203 @@
+use cgmath::{vec3, Vector3};
+use cgmath::prelude::*;
+use color::Color;
+use hitable::HitRecord;
+use material::{Material, next_material_id};
+use medium::Medium;
+use microfacet::Ggx;
+use rand::random;
+use ray::Ray;
+use sampling::Onb;
+use std::sync::Arc;
+use texture::ScalarTexture;
+
+/// Bounces between the coat and the base that are always followed inside the layer. After
+/// them paths are ended by Russian roulette, which keeps the estimate unbiased.
+const MIN_LAYER_BOUNCES: u32 = 4;
+
+/// Highest probability of following a path through another bounce, so that paths in coats
+/// that hardly absorb end too.
+const MAX_SURVIVAL: f64 = 0.95;
+
+/// Dielectric coat over another material, such as car paint, varnished wood or glossy
+/// plastic. Light is either reflected by the coat, or refracted into it and bounced between
+/// the base and the underside of the coat until it gets out again, absorbed by the coat on
+/// every crossing. Following that random walk, instead of adding up the layers, keeps the
+/// material from reflecting more light than it receives.
+pub struct CoatedMaterial {
+    base: Arc<Material>,
+    refraction_index: f64,
+    roughness: Box<ScalarTexture>,
+    tint: Option<Medium>,
+    thickness: f64,
+    id: usize,
+}
+
+impl CoatedMaterial {
+    pub fn new(base: Arc<Material>, refraction_index: f64, roughness: Box<ScalarTexture>) -> CoatedMaterial {
+        CoatedMaterial { base, refraction_index, roughness, tint: None, thickness: 0.0, id: next_material_id() }
+    }
+
+    /// Makes the coat absorb light. `tint` is the color light takes on when crossing a coat of
+    /// unit thickness straight down, and `thickness` is the thickness of this coat.
+    pub fn with_tint(mut self, tint: Color, thickness: f64) -> CoatedMaterial {
+        self.tint = Some(Medium::from_color_at_distance(tint, 1.0));
+        self.thickness = thickness;
+        self
+    }
+
+    /// Fraction of the light left after crossing the coat in direction `d`, given in the local
+    /// frame of the surface.
+    fn transmittance(&self, d: Vector3<f64>) -> Color {
+        match self.tint {
+            Some(ref medium) => medium.transmittance(self.thickness / d.z.abs().max(1e-4)),
+            None => Color::white(),
+        }
+    }
+}
+
+impl Material for CoatedMaterial {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        // The coat is only on the outside.
+        if !rec.front_face {
+            return self.base.scatter(r_in, rec);
+        }
+
+        let roughness = self.roughness.value(rec.u, rec.v, &rec.p);
+        let coat = Ggx::new(roughness, roughness);
+        let frame = Onb::from_normal(rec.normal);
+        let wo = frame.to_local(-r_in.direction.normalize());
+
+        let (mut d, weight) = coat.sample_dielectric(wo, self.refraction_index)?;
+        let mut attenuation = weight * Color::white();
+        if d.z > 0.0 {
+            return Some((Ray::new(rec.p, frame.to_world(d), r_in.time), attenuation));
+        }
+
+        let mut bounces = 0;
+        loop {
+            bounces += 1;
+            if bounces > MIN_LAYER_BOUNCES {
+                let survival = attenuation.r.max(attenuation.g).max(attenuation.b).min(MAX_SURVIVAL);
+                if random::<f64>() >= survival {
+                    return None;
+                }
+                attenuation /= survival;
+            }
+
+            // Down through the coat onto the base.
+            attenuation = attenuation * self.transmittance(d);
+            let mut down = Ray::new(rec.p, frame.to_world(d), r_in.time);
+            down.wavelength = r_in.wavelength;
+            let (scattered, base_attenuation) = self.base.scatter(&down, &rec.seen_by(&down))?;
+            attenuation = attenuation * base_attenuation;
+
+            let up = frame.to_local(scattered.direction.normalize());
+            if up.z <= 0.0 {
+                // Transmitted by the base, the coat is left behind.
+                return Some((scattered, attenuation));
+            }
+
+            // Back up through the coat to its underside, where it is seen from below.
+            attenuation = attenuation * self.transmittance(up);
+            let (flipped, weight) = coat.sample_dielectric(vec3(-up.x, -up.y, up.z), 1.0 / self.refraction_index)?;
+            attenuation = attenuation * weight;
+            d = vec3(flipped.x, flipped.y, -flipped.z);
+            if d.z > 0.0 {
+                return Some((Ray::new(rec.p, frame.to_world(d), r_in.time), attenuation));
+            }
+        }
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base.albedo(rec)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Point3;
+    use material::{Dielectric, Lambertian, Metal, MixMaterial};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Hit by a ray arriving at 40 degrees from the geometric normal `+z`, with the given
+    /// shading normal.
+    fn hit_from_above(material: &Arc<Material>, shading_normal: Vector3<f64>) -> (Ray, HitRecord) {
+        let direction = vec3(40f64.to_radians().sin(), 0.0, -40f64.to_radians().cos());
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0) - direction, direction, 0.0);
+        let rec = HitRecord::new(&ray, 1.0, vec3(0.0, 0.0, 1.0), (0.0, 0.0), material, 0, 0).with_shading_normal(shading_normal);
+        (ray, rec)
+    }
+
+    #[test]
+    fn clear_coats_over_non_lambertian_bases_conserve_energy() {
+        const RAYS: usize = 50_000;
+        let glass: Arc<Material> = Arc::new(Dielectric::new(1.5));
+        let mirror: Arc<Material> = Arc::new(Metal::new(Color::white(), 0.0));
+        let white: Arc<Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        let mix: Arc<Material> = Arc::new(MixMaterial::new(glass.clone(), white, Box::new(0.5)));
+        // Fractions of the light going into the surface: glass lets most of it through, a
+        // mirror none and the mix some.
+        for (name, base, transmitted) in [("glass", glass, 0.8..0.95), ("mirror", mirror, 0.0..0.001), ("mix", mix, 0.55..0.75)] {
+            let material: Arc<Material> = Arc::new(CoatedMaterial::new(base, 1.5, Box::new(0.1)));
+            let (ray, rec) = hit_from_above(&material, vec3(0.0, 0.0, 1.0));
+            let (mut throughput, mut down) = (0.0, 0);
+            for _ in 0..RAYS {
+                if let Some((scattered, attenuation)) = material.scatter(&ray, &rec) {
+                    throughput += attenuation.luminance();
+                    if scattered.direction.z < 0.0 {
+                        down += 1;
+                    }
+                }
+            }
+            let (throughput, down) = (throughput / RAYS as f64, down as f64 / RAYS as f64);
+            assert!((throughput - 1.0).abs() < 0.02, "{} base returns {} of the light", name, throughput);
+            assert!(transmitted.contains(&down), "{} base transmits {} of the light", name, down);
+        }
+    }
+
+    /// Base that counts the records it is given whose side of the surface doesn't match the
+    /// ray, and reflects everything back up along the shading normal.
+    struct Probe {
+        mismatches: AtomicUsize,
+    }
+
+    impl Material for Probe {
+        fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+            if rec.front_face != (r_in.direction.dot(rec.geometric_normal) < 0.0) {
+                self.mismatches.fetch_add(1, Ordering::Relaxed);
+            }
+            Some((Ray::new(rec.p, rec.normal, r_in.time), Color::new(0.9, 0.9, 0.9)))
+        }
+
+        fn albedo(&self, _rec: &HitRecord) -> Color {
+            Color::white()
+        }
+
+        fn id(&self) -> usize {
+            0
+        }
+    }
+
+    #[test]
+    fn bases_see_the_side_the_bounced_ray_arrives_from() {
+        let probe = Arc::new(Probe { mismatches: AtomicUsize::new(0) });
+        let material: Arc<Material> = Arc::new(CoatedMaterial::new(probe.clone(), 1.5, Box::new(0.5)));
+        // Tilted this far, many of the rays the coat bounces back down arrive from below the
+        // geometric surface.
+        let (ray, rec) = hit_from_above(&material, vec3(1.0, 0.0, 0.8).normalize());
+        for _ in 0..10_000 {
+            material.scatter(&ray, &rec);
+        }
+        assert_eq!(probe.mismatches.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,137 @@
+use cgmath::{vec2, Vector2};
+use cgmath::prelude::*;
+use image;
+use rand::random;
+use std::f64::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Shape of the aperture, which is also the shape of out of focus highlights.
+#[derive(Clone)]
+pub enum ApertureShape {
+    Circular,
+    /// Regular polygon formed by `blades` straight blades, rotated by `rotation` radians.
+    Polygon { blades: u32, rotation: f64 },
+    Mask(Arc<ApertureMask>),
+}
+
+impl ApertureShape {
+    /// Samples a point on the aperture, scaled so that the shape fits in the unit disc.
+    pub fn sample(&self) -> Vector2<f64> {
+        match *self {
+            ApertureShape::Circular => random_in_unit_disc(),
+            ApertureShape::Polygon { blades, rotation } => random_in_polygon(blades, rotation),
+            ApertureShape::Mask(ref mask) => mask.sample(),
+        }
+    }
+}
+
+/// Aperture given as a grayscale image, where white is open and black is blocked. The image
+/// is stretched over the square enclosing the unit disc.
+pub struct ApertureMask {
+    width: u32,
+    height: u32,
+    transmission: Vec<f64>,
+}
+
+impl ApertureMask {
+    pub fn open(path: &Path) -> image::ImageResult<ApertureMask> {
+        let img = image::open(path)?.to_luma();
+        let (width, height) = img.dimensions();
+        let transmission = img.pixels().map(|p| p.data[0] as f64 / 255.0).collect();
+        Ok(ApertureMask { width, height, transmission })
+    }
+
+    fn transmission_at(&self, p: Vector2<f64>) -> f64 {
+        let x = (((p.x + 1.0) / 2.0 * self.width as f64) as u32).min(self.width - 1);
+        let y = (((1.0 - p.y) / 2.0 * self.height as f64) as u32).min(self.height - 1);
+        self.transmission[(y * self.width + x) as usize]
+    }
+
+    fn sample(&self) -> Vector2<f64> {
+        for _ in 0..256 {
+            let p = vec2(2.0 * random::<f64>() - 1.0, 2.0 * random::<f64>() - 1.0);
+            if random::<f64>() < self.transmission_at(p) {
+                return p;
+            }
+        }
+
+        vec2(0.0, 0.0)
+    }
+}
+
+/// Brown–Conrady lens distortion with radial coefficients `k1`, `k2`, `k3` and tangential
+/// coefficients `p1`, `p2`, applied to film coordinates normalized by the half height.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LensDistortion {
+    pub k1: f64,
+    pub k2: f64,
+    pub k3: f64,
+    pub p1: f64,
+    pub p2: f64,
+}
+
+impl LensDistortion {
+    pub fn radial(k1: f64, k2: f64, k3: f64) -> LensDistortion {
+        LensDistortion { k1, k2, k3, p1: 0.0, p2: 0.0 }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.k1 == 0.0 && self.k2 == 0.0 && self.k3 == 0.0 && self.p1 == 0.0 && self.p2 == 0.0
+    }
+
+    /// Maps an undistorted point to where the lens images it.
+    pub fn distort(&self, p: Vector2<f64>) -> Vector2<f64> {
+        let r2 = p.magnitude2();
+        let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+        vec2(
+            p.x * radial + 2.0 * self.p1 * p.x * p.y + self.p2 * (r2 + 2.0 * p.x * p.x),
+            p.y * radial + self.p1 * (r2 + 2.0 * p.y * p.y) + 2.0 * self.p2 * p.x * p.y,
+        )
+    }
+
+    /// Inverts `distort` by fixed point iteration: finds the undistorted point that the lens
+    /// images at the given film position. Strong barrel distortion does not reach the corners
+    /// of the film, in which case there is no such point.
+    pub fn undistort(&self, distorted: Vector2<f64>) -> Option<Vector2<f64>> {
+        let mut p = distorted;
+        for _ in 0..20 {
+            p += distorted - self.distort(p);
+        }
+
+        if (self.distort(p) - distorted).magnitude2() < 1e-12 {
+            Some(p)
+        } else {
+            None
+        }
+    }
+}
+
+fn random_in_unit_disc() -> Vector2<f64> {
+    loop {
+        let v = vec2(2.0 * random::<f64>() - 1.0, 2.0 * random::<f64>() - 1.0);
+        if v.magnitude2() < 1.0 {
+            return v;
+        }
+    }
+}
+
+/// Picks one of the triangles between the center and two neighbouring blade corners and
+/// samples it uniformly.
+fn random_in_polygon(blades: u32, rotation: f64) -> Vector2<f64> {
+    let blades = blades.max(3);
+    let sector = (random::<f64>() * blades as f64) as u32 % blades;
+    let angle0 = rotation + 2.0 * PI * sector as f64 / blades as f64;
+    let angle1 = angle0 + 2.0 * PI / blades as f64;
+    let a = vec2(angle0.cos(), angle0.sin());
+    let b = vec2(angle1.cos(), angle1.sin());
+
+    let mut u = random::<f64>();
+    let mut v = random::<f64>();
+    if u + v > 1.0 {
+        u = 1.0 - u;
+        v = 1.0 - v;
+    }
+
+    u * a + v * b
+}
@@ -4,149 +4,367 @@ extern crate image;
 extern crate num_cpus;
 extern crate rand;
 
+use bump::Bumped;
 use bvh_node::BvhNode;
-use camera::Camera;
-use cgmath::{Point3, vec3};
+use camera::{Camera, film_coordinates, OrthographicCamera, PerspectiveCamera, Shutter};
+use animation::{CameraPath, Interpolation, Keyframes, TransformKeyframes};
+use cgmath::{Deg, Point3, Quaternion, Vector3, vec3};
 use cgmath::prelude::*;
 use color::Color;
+use cutout::Cutout;
 use hitable::Hitable;
 use hitable_list::HitableList;
-use image::{ImageBuffer, Rgb};
-use material::{Dielectric, Lambertian, Metal};
-use perlin::NoiseTexture;
-use rand::{random, Rng, thread_rng};
-use ray::Ray;
+use layered::CoatedMaterial;
+use lens::{ApertureMask, ApertureShape};
+use medium::Medium;
+use material::{ComplexIor, Conductor, DiffuseLight, Dielectric, Dispersion, Lambertian, Material, Metal, MixMaterial, OrenNayar, RoughDielectric};
+use instance::{AnimatedInstance, Instance, Transform};
+use one_sided::OneSided;
+use options::{Options, Projection, SceneName};
+use panorama::{CubeMapCamera, EquirectangularCamera, FisheyeCamera, OdsCamera};
+use perlin::{NoiseTexture, Octaves, Perlin};
+use principled::Principled;
+use procedural::{ColorRamp, DomainWarp, Fbm, Marble, RampTexture, Ridged, Turbulence, Wood, Worley, WorleyFeature};
+use quad::Quad;
+use rand::{Rng, SeedableRng, XorShiftRng};
+use spectrum::Spd;
 use sphere::{MovingSphere, Sphere};
-use std::io::prelude::*;
-use std::sync::{Arc, Mutex};
-use std::thread;
+use subsurface::Subsurface;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Instant;
-use texture::CheckerTexture;
+use texture::{CheckerTexture, ImageTexture, RemapTexture, ScalarCheckerTexture, ScalarTexture, Texture};
+use thinfilm::ThinFilm;
+use triangle::Mesh;
 
 mod aabb;
+mod animation;
+mod bump;
 mod bvh_node;
 mod camera;
 mod color;
+mod cutout;
+mod denoise;
+mod film;
 mod hitable;
 mod hitable_list;
+mod instance;
+mod layered;
+mod lens;
 mod material;
+mod medium;
+mod microfacet;
+mod one_sided;
+mod options;
+mod panorama;
 mod perlin;
+mod physical;
+mod principled;
+mod procedural;
+mod quad;
 mod ray;
+mod render;
+mod sampling;
+mod spectrum;
 mod sphere;
+mod subsurface;
 mod texture;
+mod thinfilm;
+mod triangle;
 
-fn color<T: Hitable + ?Sized>(ray: &Ray, world: &T, depth: u32) -> Color {
-    if let Some(rec) = world.hit(ray, 0.001, f64::max_value()) {
-        if depth >= 50 {
-            return Color::black();
-        }
+fn main() {
+    let options = Options::from_args();
+    let nx = options.width;
+    let ny = options.height;
+    let ns = options.samples;
+
+    let camera_path = match options.scene {
+        SceneName::Turntable => turntable_camera(),
+        _ => CameraPath::fixed(Point3::new(13.0, 2.0, 3.0), Point3::new(0.0, 0.0, 0.0), 20.0),
+    };
+
+    let mut channels = options.aovs.clone();
+    if options.denoise {
+        channels.extend_from_slice(&denoise::FEATURE_CHANNELS);
+    }
+
+    let (first_frame, last_frame) = options.frames.unwrap_or((0, 0));
+    let now = Instant::now();
+
+    for frame in first_frame..last_frame + 1 {
+        let (time0, time1) = shutter_interval(&options, frame);
+        let shutter = Shutter::new(time0, time1).with_curve(&options.shutter_curve).with_rolling(options.rolling_shutter);
+        let world = Arc::new(build_world(&options, shutter.time0, shutter.closing_time()));
+        let camera = build_camera(&options, &camera_path, &**world, shutter);
 
-        if let Some((scattered, attenuation)) = rec.material.scatter(ray, &rec) {
-            let col = color(&scattered, world, depth + 1);
-            return attenuation * col;
-        } else {
-            return Color::black();
+        let mut film = render::render(camera, world, nx, ny, ns, &channels, options.spectral);
+        if options.denoise {
+            denoise::denoise(&mut film);
         }
-    } else {
-        let unit_direction = ray.direction.normalize();
-        let t = 0.5 * (unit_direction.y + 1.0);
-        return (1.0 - t) * Color::white() + t * Color::new(0.5, 0.7, 1.0);
+        film.expose(options.physical.map_or(1.0, |settings| settings.exposure()));
+
+        let output = if options.frames.is_some() { frame_path(&options.output, frame) } else { options.output.clone() };
+        film.save(&output, &options.aovs).unwrap();
     }
+
+    let elapsed_seconds = now.elapsed().as_secs_f64();
+    let samples = (nx * ny * ns) as u64 * (last_frame - first_frame + 1) as u64;
+    println!("\nrendered {} samples in {:.1} seconds ({:.0} samples/s)", samples, elapsed_seconds, samples as f64 / elapsed_seconds);
 }
 
-fn main() {
-    let nx = 600;
-    let ny = 400;
-    let ns = 50;
-    let lookfrom = Point3::new(13.0, 2.0, 3.0);
-    let lookat = Point3::new(0.0, 0.0, 0.0);
-    let dist_to_focus = 10.0;
-    let aperture = 0.0;
-    let time0 = 0.0;
-    let time1 = 1.0;
+/// Times when the shutter opens and closes for the given frame. A single still opens the
+/// shutter for one unit of time unless the physical camera says otherwise, frames of a
+/// sequence open it at their own time for the duration given by the shutter angle.
+fn shutter_interval(options: &Options, frame: u32) -> (f64, f64) {
+    if options.frames.is_none() {
+        return (0.0, options.physical.map_or(1.0, |settings| settings.shutter_duration));
+    }
+
+    let time0 = frame as f64 / options.fps;
+    let duration = match options.physical {
+        Some(settings) => settings.shutter_duration,
+        None => options.shutter_angle / 360.0 / options.fps,
+    };
+    (time0, time0 + duration)
+}
 
+/// Inserts the frame number before the extension, `output.png` becomes `output.0001.png`.
+fn frame_path(output: &Path, frame: u32) -> PathBuf {
+    let stem = output.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+    let extension = output.extension().and_then(|s| s.to_str()).unwrap_or("png");
+    output.with_file_name(format!("{}.{:04}.{}", stem, frame, extension))
+}
+
+fn build_camera(options: &Options, path: &CameraPath, world: &Hitable, shutter: Shutter) -> Arc<Camera> {
+    let nx = options.width;
+    let ny = options.height;
+    let lookfrom = path.position.at(shutter.time0);
+    let lookat = path.target.at(shutter.time0);
     let up = vec3(0.0, 1.0, 0.0);
     let aspect = nx as f64 / ny as f64;
 
-    let camera = Arc::new(Camera::new(lookfrom, lookat, up, 20.0, aspect, aperture, dist_to_focus, time0, time1));
-    let mut rng = thread_rng();
-//    let world = Arc::new(random_scene(&mut rng, time0, time1));
-    let world = Arc::new(two_perlin_spheres());
-    let now = Instant::now();
+    match options.projection {
+        Projection::Perspective => {
+            let (vfov, aperture) = match options.physical {
+                Some(ref settings) => (settings.vfov(aspect), settings.aperture()),
+                None => (path.vfov.at(shutter.time0), options.aperture),
+            };
+
+            let aperture_shape = if let Some(ref path) = options.aperture_mask {
+                ApertureShape::Mask(Arc::new(ApertureMask::open(path).expect("could not load aperture mask")))
+            } else if let Some(blades) = options.aperture_blades {
+                ApertureShape::Polygon { blades, rotation: options.blade_rotation.to_radians() }
+            } else {
+                ApertureShape::Circular
+            };
+
+            let mut camera = PerspectiveCamera::new(lookfrom, lookat, up, vfov, aspect, aperture, options.focus_dist, shutter)
+                .with_aperture_shape(aperture_shape)
+                .with_cat_eye(options.cat_eye)
+                .with_tilt(options.tilt.0, options.tilt.1)
+                .with_shift(options.shift.0, options.shift.1)
+                .with_distortion(options.distortion);
+
+            if let Some((x, y)) = options.autofocus {
+                let (s, t) = film_coordinates(x + 0.5, y + 0.5, nx, ny);
+                camera = camera.autofocus(world, s, t);
+            }
 
-    let cpus = num_cpus::get();
+            Arc::new(camera)
+        }
+        Projection::Orthographic => Arc::new(OrthographicCamera::new(lookfrom, lookat, up, 5.0, aspect, shutter)),
+        Projection::Fisheye(mapping) => Arc::new(FisheyeCamera::new(lookfrom, lookat, up, 180.0, aspect, mapping, shutter)),
+        Projection::Equirectangular => Arc::new(EquirectangularCamera::new(lookfrom, lookat, up, shutter)),
+        Projection::CubeMap => Arc::new(CubeMapCamera::new(lookfrom, lookat, up, shutter)),
+        Projection::OmnidirectionalStereo(layout) => Arc::new(OdsCamera::new(lookfrom, lookat, up, options.ipd, layout, shutter)),
+    }
+}
 
-    let arc_img = Arc::new(Mutex::new(ImageBuffer::new(nx, ny)));
-    let mut threads = Vec::new();
-    let y_counter = Arc::new(Mutex::new(0));
+fn build_world(options: &Options, t0: f64, t1: f64) -> Box<Hitable> {
+    match options.scene {
+        SceneName::TwoPerlinSpheres => two_perlin_spheres(),
+        SceneName::Random => {
+            // Fixed seed so that every frame of a sequence gets the same scene.
+            let mut rng = XorShiftRng::from_seed([0x193a_6754, 0xa8a7_d469, 0x9783_0e05, 0x113b_a7bb]);
+            random_scene(&mut rng, t0, t1)
+        }
+        SceneName::Turntable => turntable(t0, t1),
+        SceneName::Glass => glass(t0, t1),
+        SceneName::Materials => materials(t0, t1),
+        SceneName::Mix => {
+            let mask: Box<ScalarTexture> = match options.mask {
+                Some(ref path) => Box::new(ImageTexture::open(path).expect("could not load mask")),
+                None => Box::new(ScalarCheckerTexture::new(Box::new(1.0), Box::new(0.0))),
+            };
+            mix(mask, t0, t1)
+        }
+        SceneName::Translucent => translucent(t0, t1),
+        SceneName::ThinFilm => thin_film(t0, t1),
+        SceneName::Bumps => {
+            let normal_map = options.normal_map.as_ref().map(|path| ImageTexture::open_data(path).expect("could not load normal map"));
+            bumps(normal_map, t0, t1)
+        }
+        SceneName::Cutout => {
+            let leaf = options.mask.as_ref().map(|path| ImageTexture::open_alpha(path).expect("could not load mask"));
+            cutout(leaf, t0, t1)
+        }
+        SceneName::Room => room(t0, t1),
+        SceneName::Procedural => {
+            let texture = options.texture.as_ref().map(|spec| procedural::parse_texture(spec).expect("invalid texture"));
+            procedural(texture, t0, t1)
+        }
+    }
+}
 
-    for _ in 0..cpus {
-        let arc_img = arc_img.clone();
-        let camera = camera.clone();
-        let y_counter = y_counter.clone();
-        let world = world.clone();
+/// Camera that slowly dollies in while the turntable spins.
+fn turntable_camera() -> CameraPath {
+    CameraPath {
+        position: Keyframes::new(Interpolation::Bezier)
+            .key(0.0, Point3::new(10.0, 3.0, 6.0))
+            .key(2.0, Point3::new(8.0, 2.5, 5.0))
+            .key(4.0, Point3::new(7.0, 2.0, 3.0)),
+        target: Keyframes::constant(Point3::new(0.0, 1.0, 0.0)),
+        vfov: Keyframes::new(Interpolation::Bezier)
+            .key(0.0, 30.0)
+            .key(4.0, 25.0),
+    }
+}
 
-        threads.push(thread::spawn(move || {
-            let camera= &camera;
-            let world: &Hitable = &**world;
-            let mut row = Vec::with_capacity(nx as usize);
+/// A few objects on a turntable making a full turn every four seconds, blurred by the
+/// rotation during the shutter interval.
+fn turntable(t0: f64, t1: f64) -> Box<Hitable> {
+    let objects: Vec<Box<Hitable>> = vec![
+        Box::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, Arc::new(Lambertian::new(Box::new(NoiseTexture::new(4.0)))))),
+        Box::new(Sphere::new(Point3::new(2.0, 0.5, 0.0), 0.5, Arc::new(Conductor::new(ComplexIor::gold(), 0.2)))),
+        Box::new(Sphere::new(Point3::new(-1.0, 0.5, 1.7), 0.5, Arc::new(RoughDielectric::new(1.5, Box::new(0.3)).with_absorption(Medium::from_color_at_distance(Color::new(0.3, 0.8, 0.5), 0.5))))),
+        Box::new(Sphere::new(Point3::new(-1.0, 0.5, -1.7), 0.5, Arc::new(Dielectric::new(1.33).with_absorption(Medium::from_color_at_distance(Color::new(0.9, 0.5, 0.1), 0.4))))),
+        Box::new(Sphere::new(Point3::new(1.0, 0.3, 1.7), 0.3, Arc::new(Conductor::new(ComplexIor::copper(), 0.35)))),
+        Box::new(Sphere::new(Point3::new(1.0, 0.3, -1.7), 0.3, Arc::new(Conductor::new(ComplexIor::silver(), 0.0)))),
+    ];
+
+    let y = vec3(0.0, 1.0, 0.0);
+    let mut rotation = Keyframes::new(Interpolation::Linear);
+    for i in 0..5 {
+        rotation = rotation.key(i as f64, Quaternion::from_axis_angle(y, Deg(90.0 * i as f64)));
+    }
+    let spin = TransformKeyframes {
+        translation: Keyframes::constant(vec3(0.0, 0.0, 0.0)),
+        rotation,
+        scale: Keyframes::constant(vec3(1.0, 1.0, 1.0)),
+    };
 
-            loop {
-                let y = get_and_increment(&y_counter);
-                if y >= ny {
-                    break;
-                }
+    // Brushed aluminum, with the grooves running around the spindle.
+    let platter = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Conductor::anisotropic(ComplexIor::aluminum(), 0.15, 0.5)));
+    let platter_transform = Transform::new(vec3(0.0, 0.0, 0.0), Quaternion::one(), vec3(3.0, 0.05, 3.0));
 
-                print!("\r{}/{}", y + 1, ny);
-                std::io::stdout().flush().expect("Could not flush stdout");
+    let base_texture = CheckerTexture::new(Box::new(Color::new(0.2, 0.3, 0.1)), Box::new(Color::new(0.9, 0.9, 0.9)));
+    let vec: Vec<Box<Hitable>> = vec![
+        Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(Box::new(base_texture))))),
+        Box::new(Instance::new(Box::new(platter), platter_transform)),
+        Box::new(AnimatedInstance::new(Box::new(HitableList::new(objects)), spin)),
+    ];
 
-                row.clear();
-                for x in 0..nx {
-                    let i = x;
-                    let j = ny - y;
+    BvhNode::build(vec, t0, t1)
+}
 
-                    let mut col = Color::black();
-                    for _ in 0..ns {
-                        let u = (i as f64 + random::<f64>()) / (nx as f64);
-                        let v = (j as f64 + random::<f64>()) / (ny as f64);
+/// Spheres of different dispersive glasses and diamond on a checkered floor, which shows
+/// the colored fringes of refraction, and one of frosted flint glass. Behind them are a warm incandescent light and a
+/// fluorescent tube-like light with a spiky spectrum.
+fn glass(t0: f64, t1: f64) -> Box<Hitable> {
+    let base_texture = CheckerTexture::new(Box::new(Color::new(0.05, 0.05, 0.05)), Box::new(Color::new(0.9, 0.9, 0.9)));
+    let glasses = [Dispersion::crown(), Dispersion::bk7(), Dispersion::flint(), Dispersion::diamond()];
 
-                        let ray = camera.get_ray(u, v);
-                        col += color(&ray, world, 0);
-                    }
+    let mut vec: Vec<Box<Hitable>> = vec![];
+    vec.push(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(Box::new(base_texture))))));
+    for (i, dispersion) in glasses.iter().enumerate() {
+        let center = Point3::new(0.0, 0.8, 1.8 * i as f64 - 2.7);
+        vec.push(Box::new(Sphere::new(center, 0.8, Arc::new(Dielectric::dispersive(*dispersion)))));
+    }
+    let frosted = RoughDielectric::dispersive(Dispersion::flint(), Box::new(0.1));
+    vec.push(Box::new(Sphere::new(Point3::new(1.8, 0.4, 0.0), 0.4, Arc::new(frosted))));
 
-                    col /= ns as f64;
-                    col = col.gamma_correct();
+    let incandescent = DiffuseLight::new(Spd::blackbody(2700.0), 4.0);
+    let fluorescent = DiffuseLight::new(fluorescent_spd(), 4.0);
+    vec.push(Box::new(Sphere::new(Point3::new(-3.0, 2.5, -2.0), 0.6, Arc::new(incandescent))));
+    vec.push(Box::new(Sphere::new(Point3::new(-3.0, 2.5, 2.0), 0.6, Arc::new(fluorescent))));
 
-                    let ir = (255.99 * col.r) as u8;
-                    let ig = (255.99 * col.g) as u8;
-                    let ib = (255.99 * col.b) as u8;
+    BvhNode::build(vec, t0, t1)
+}
 
-                    row.push(Rgb([ir, ig, ib]));
-                }
+/// A row of spheres showing what the principled material can do: clearcoated plastic,
+/// brushed gold, velvet, glass and tinted specular. Next to them are coated materials and
+/// rough clay.
+fn materials(t0: f64, t1: f64) -> Box<Hitable> {
+    let principled = vec![
+        Principled::new(Box::new(Color::new(0.6, 0.05, 0.05)))
+            .with_roughness(Box::new(0.6))
+            .with_clearcoat(Box::new(1.0), Box::new(0.9)),
+        Principled::new(Box::new(Color::new(1.0, 0.77, 0.34)))
+            .with_metallic(Box::new(1.0))
+            .with_roughness(Box::new(0.4))
+            .with_anisotropy(Box::new(0.8)),
+        Principled::new(Box::new(Color::new(0.25, 0.05, 0.3)))
+            .with_roughness(Box::new(1.0))
+            .with_sheen(Box::new(1.0), Box::new(0.5)),
+        Principled::new(Box::new(Color::new(0.9, 0.95, 1.0)))
+            .with_roughness(Box::new(0.1))
+            .with_transmission(Box::new(1.0), Box::new(1.45)),
+        Principled::new(Box::new(Color::new(0.1, 0.2, 0.6)))
+            .with_roughness(Box::new(0.2))
+            .with_specular(Box::new(1.0))
+            .with_specular_tint(Box::new(1.0)),
+    ];
+    let materials: Vec<Arc<Material>> = principled.into_iter().map(|m| Arc::new(m) as Arc<Material>)
+        .chain(vec![
+            // Metallic car paint under a clear coat.
+            Arc::new(CoatedMaterial::new(Arc::new(Conductor::new(ComplexIor::copper(), 0.5)), 1.5, Box::new(0.0))) as Arc<Material>,
+            // Varnished wood, the varnish darkening and yellowing it.
+            Arc::new(CoatedMaterial::new(Arc::new(Lambertian::new(Box::new(Color::new(0.5, 0.3, 0.15)))), 1.5, Box::new(0.1))
+                .with_tint(Color::new(0.9, 0.7, 0.4), 0.5)),
+            // Unglazed clay.
+            Arc::new(OrenNayar::new(Box::new(Color::new(0.6, 0.35, 0.25)), 30.0)) as Arc<Material>,
+        ])
+        .collect();
 
-                let mut img = arc_img.lock().expect("could not lock image");
-                for (x, color) in row.iter().enumerate() {
-                    img.put_pixel(x as u32, y, *color);
-                }
-            }
-        }));
+    let base_texture = CheckerTexture::new(Box::new(Color::new(0.2, 0.3, 0.1)), Box::new(Color::new(0.9, 0.9, 0.9)));
+    let mut vec: Vec<Box<Hitable>> = vec![];
+    vec.push(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(Box::new(base_texture))))));
+    for (i, material) in materials.into_iter().enumerate() {
+        let center = Point3::new(0.0, 0.45, 1.05 * i as f64 - 3.675);
+        vec.push(Box::new(Sphere::new(center, 0.45, material)));
     }
 
-    for thread in threads {
-        let _ = thread.join();
-    }
+    BvhNode::build(vec, t0, t1)
+}
 
-    let elapsed_seconds = now.elapsed().as_secs();
-    let samples = (nx * ny * ns) as u64;
-    println!("\nrendered {} samples in {} seconds ({} samples/s)", samples, elapsed_seconds, samples / elapsed_seconds);
-    arc_img.lock().unwrap().save("images/output.png").unwrap();
+/// Materials blended by masks: wet patches on the ground, rust on steel, and gold inlaid in
+/// red paint following the given mask.
+fn mix(mask: Box<ScalarTexture>, t0: f64, t1: f64) -> Box<Hitable> {
+    let dry: Arc<Material> = Arc::new(Lambertian::new(Box::new(Color::new(0.3, 0.3, 0.3))));
+    let wet: Arc<Material> = Arc::new(Principled::new(Box::new(Color::new(0.08, 0.08, 0.08))).with_roughness(Box::new(0.05)));
+    let steel: Arc<Material> = Arc::new(Conductor::new(ComplexIor::aluminum(), 0.3));
+    let rust: Arc<Material> = Arc::new(Lambertian::new(Box::new(Color::new(0.4, 0.15, 0.05))));
+    let gold: Arc<Material> = Arc::new(Conductor::new(ComplexIor::gold(), 0.1));
+    let paint: Arc<Material> = Arc::new(Lambertian::new(Box::new(Color::new(0.5, 0.05, 0.05))));
+
+    let vec: Vec<Box<Hitable>> = vec![
+        Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(MixMaterial::new(dry, wet, Box::new(NoiseTexture::new(1.0)))))),
+        Box::new(Sphere::new(Point3::new(0.0, 1.0, -1.2), 1.0, Arc::new(MixMaterial::new(steel, rust, Box::new(NoiseTexture::new(4.0)))))),
+        Box::new(Sphere::new(Point3::new(0.0, 1.0, 1.2), 1.0, Arc::new(MixMaterial::new(paint, gold, mask)))),
+    ];
+
+    BvhNode::build(vec, t0, t1)
 }
 
-fn get_and_increment(counter: &Arc<Mutex<u32>>) -> u32 {
-    let mut shared_y = counter.lock().expect("locking counter failed");
-    let value = *shared_y;
-    *shared_y = value + 1;
-    value
+/// Spectrum resembling a cool white fluorescent lamp: a broad phosphor emission with the
+/// narrow lines of mercury on top.
+fn fluorescent_spd() -> Spd {
+    let mut table = vec![(380.0, 0.05), (450.0, 0.2), (580.0, 0.35), (650.0, 0.15), (780.0, 0.02)];
+    for &(line, power) in &[(404.7, 0.6), (435.8, 1.5), (546.1, 2.0), (578.0, 0.7), (611.0, 1.0)] {
+        let base = Spd::tabulated(table.clone()).value(line);
+        table.extend_from_slice(&[(line - 2.0, base), (line, base + power), (line + 2.0, base)]);
+    }
+    Spd::tabulated(table)
 }
 
 #[allow(dead_code)]
@@ -154,7 +372,8 @@ fn two_perlin_spheres() -> Box<Hitable> {
     let mut vec: Vec<Box<Hitable>> = vec![];
 
     vec.push(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(Box::new(NoiseTexture::new(4.0)))))));
-    vec.push(Box::new(Sphere::new(Point3::new(0.0, 2.0, 0.0), 2.0, Arc::new(Lambertian::new(Box::new(NoiseTexture::new(4.0)))))));
+    let sphere = NoiseTexture::new(4.0).with_noise(Perlin::new(1));
+    vec.push(Box::new(Sphere::new(Point3::new(0.0, 2.0, 0.0), 2.0, Arc::new(Lambertian::new(Box::new(sphere))))));
 
     Box::new(HitableList::new(vec))
 }
@@ -194,3 +413,224 @@ fn random_scene<T : Rng>(rng: &mut T, t0: f64, t1: f64) -> Box<Hitable> {
 
     BvhNode::build(vec, t0, t1)
 }
+
+/// Translucent materials lit from behind: marble, candle wax, skin and green soap.
+fn translucent(t0: f64, t1: f64) -> Box<Hitable> {
+    let materials = vec![
+        Subsurface::new(Color::new(0.9, 0.88, 0.85), Color::new(0.05, 0.05, 0.05), 1.5)
+            .with_roughness(Box::new(0.2)),
+        Subsurface::new(Color::new(0.95, 0.85, 0.6), Color::new(0.3, 0.2, 0.1), 1.45),
+        // Red light travels furthest through skin, which is why backlit ears glow red.
+        Subsurface::new(Color::new(0.85, 0.6, 0.45), Color::new(0.4, 0.12, 0.06), 1.4)
+            .with_roughness(Box::new(0.4))
+            .with_anisotropy(0.8),
+        Subsurface::new(Color::new(0.5, 0.9, 0.4), Color::new(0.5, 1.0, 0.4), 1.5),
+    ];
+
+    let base_texture = CheckerTexture::new(Box::new(Color::new(0.2, 0.2, 0.2)), Box::new(Color::new(0.8, 0.8, 0.8)));
+    let mut vec: Vec<Box<Hitable>> = vec![];
+    vec.push(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(Box::new(base_texture))))));
+    for (i, material) in materials.into_iter().enumerate() {
+        let center = Point3::new(0.0, 0.7, 1.6 * i as f64 - 2.4);
+        vec.push(Box::new(Sphere::new(center, 0.7, Arc::new(material))));
+    }
+    let light = DiffuseLight::new(Spd::blackbody(3200.0), 6.0);
+    vec.push(Box::new(Sphere::new(Point3::new(-4.0, 2.0, 0.0), 1.0, Arc::new(light))));
+
+    BvhNode::build(vec, t0, t1)
+}
+
+/// Iridescent thin films: a soap bubble, oxidized metal and glass with an anti-reflective
+/// coating next to uncoated glass.
+fn thin_film(t0: f64, t1: f64) -> Box<Hitable> {
+    let soap = ThinFilm::new(Box::new(RemapTexture::new(Box::new(NoiseTexture::new(2.0)), 200.0, 900.0)), 1.33);
+    let oxide = ThinFilm::new(Box::new(RemapTexture::new(Box::new(NoiseTexture::new(1.0)), 50.0, 250.0)), 2.5);
+    // A quarter of a wavelength of green light thick, so that its two reflections cancel out.
+    let coating = ThinFilm::new(Box::new(550.0 / 4.0 / 1.38), 1.38);
+
+    let materials: Vec<Arc<Material>> = vec![
+        Arc::new(Dielectric::new(1.0).with_thin_film(soap)),
+        Arc::new(Conductor::new(ComplexIor::aluminum(), 0.1).with_thin_film(oxide)),
+        Arc::new(Dielectric::new(1.5).with_thin_film(coating)),
+        Arc::new(Dielectric::new(1.5)),
+    ];
+
+    let base_texture = CheckerTexture::new(Box::new(Color::new(0.05, 0.05, 0.05)), Box::new(Color::new(0.5, 0.5, 0.5)));
+    let mut vec: Vec<Box<Hitable>> = vec![];
+    vec.push(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(Box::new(base_texture))))));
+    for (i, material) in materials.into_iter().enumerate() {
+        let center = Point3::new(0.0, 0.7, 1.6 * i as f64 - 2.4);
+        vec.push(Box::new(Sphere::new(center, 0.7, material)));
+    }
+
+    BvhNode::build(vec, t0, t1)
+}
+
+/// Bump and normal mapping: a rolling mesh ground with marble veins raised on it, or the
+/// given normal map, and spheres of pitted clay, hammered gold and rippled glass.
+fn bumps(normal_map: Option<ImageTexture>, t0: f64, t1: f64) -> Box<Hitable> {
+    let ground: Arc<Material> = Arc::new(Lambertian::new(Box::new(Color::new(0.5, 0.45, 0.4))));
+    let ground: Arc<Material> = match normal_map {
+        Some(map) => Arc::new(Bumped::normal_map(ground, Box::new(map))),
+        None => Arc::new(Bumped::height(ground, Box::new(NoiseTexture::new(1.0)), 0.15)),
+    };
+    let clay: Arc<Material> = Arc::new(Lambertian::new(Box::new(Color::new(0.7, 0.35, 0.2))));
+    let gold: Arc<Material> = Arc::new(Conductor::new(ComplexIor::gold(), 0.15));
+    let glass: Arc<Material> = Arc::new(Dielectric::new(1.5));
+
+    let mut vec = rolling_ground(10.0, 40).into_triangles(ground).expect("invalid ground mesh");
+    vec.push(Box::new(Sphere::new(Point3::new(0.0, 0.8, -1.8), 0.7, Arc::new(Bumped::height(clay, Box::new(NoiseTexture::new(4.0)), 0.01)))));
+    vec.push(Box::new(Sphere::new(Point3::new(0.0, 0.8, 0.0), 0.7, Arc::new(Bumped::height(gold, Box::new(NoiseTexture::new(20.0)), 0.005)))));
+    vec.push(Box::new(Sphere::new(Point3::new(0.0, 0.8, 1.8), 0.7, Arc::new(Bumped::height(glass, Box::new(NoiseTexture::new(2.0)), 0.02)))));
+
+    BvhNode::build(vec, t0, t1)
+}
+
+/// Smoothly rolling square of ground `2 * size` across, split into `n` by `n` cells, with
+/// vertex normals and texture coordinates repeating every two units.
+fn rolling_ground(size: f64, n: usize) -> Mesh {
+    let height = |x: f64, z: f64| 0.1 * (0.7 * x).sin() * (0.5 * z).cos();
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    for i in 0..n + 1 {
+        for j in 0..n + 1 {
+            let x = -size + 2.0 * size * i as f64 / n as f64;
+            let z = -size + 2.0 * size * j as f64 / n as f64;
+            positions.push(Point3::new(x, height(x, z), z));
+            let slope_x = 0.07 * (0.7 * x).cos() * (0.5 * z).cos();
+            let slope_z = -0.05 * (0.7 * x).sin() * (0.5 * z).sin();
+            normals.push(vec3(-slope_x, 1.0, -slope_z).normalize());
+            uvs.push((x / 2.0, -z / 2.0));
+        }
+    }
+
+    let mut faces = vec![];
+    for i in 0..n {
+        for j in 0..n {
+            let corner = |di: usize, dj: usize| (i + di) * (n + 1) + j + dj;
+            faces.push([corner(0, 0), corner(0, 1), corner(1, 1)]);
+            faces.push([corner(0, 0), corner(1, 1), corner(1, 0)]);
+        }
+    }
+
+    Mesh::new(positions, faces).with_normals(normals).with_uvs(uvs).with_generated_tangents().expect("invalid ground mesh")
+}
+
+/// Single quads with holes cut into them: a fence, a sheer curtain letting through half of the
+/// light, and a bush of leaves shaped by the alpha channel of the given image, or simple leaf
+/// shapes without one.
+fn cutout(leaf: Option<ImageTexture>, t0: f64, t1: f64) -> Box<Hitable> {
+    let ground = CheckerTexture::new(Box::new(Color::new(0.2, 0.3, 0.1)), Box::new(Color::new(0.9, 0.9, 0.9)));
+    let wood: Arc<Material> = Arc::new(Lambertian::new(Box::new(Color::new(0.45, 0.3, 0.15))));
+    let cloth: Arc<Material> = Arc::new(Lambertian::new(Box::new(Color::new(0.8, 0.2, 0.2))));
+    let green: Arc<Material> = Arc::new(Lambertian::new(Box::new(Color::new(0.15, 0.4, 0.1))));
+
+    let mut vec: Vec<Box<Hitable>> = vec![];
+    vec.push(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(Box::new(ground))))));
+
+    let fence = Quad::new(Point3::new(-1.0, 0.0, -3.0), vec3(0.0, 0.0, 2.5), vec3(0.0, 1.5, 0.0), wood);
+    vec.push(Box::new(Cutout::new(Box::new(fence), Box::new(ScalarCheckerTexture::new(Box::new(1.0), Box::new(0.0))))));
+
+    let curtain = Quad::new(Point3::new(0.5, 0.0, -0.3), vec3(0.0, 0.0, 1.2), vec3(0.0, 1.8, 0.0), cloth);
+    vec.push(Box::new(Cutout::new(Box::new(curtain), Box::new(0.5))));
+
+    // Fixed seed so that the bush looks the same in every frame.
+    let mut rng = XorShiftRng::from_seed([0x2f6b_1c9d, 0x6a09_e667, 0xbb67_ae85, 0x3c6e_f372]);
+    let mut leaves: Vec<Box<Hitable>> = vec![];
+    for _ in 0..60 {
+        let center = Point3::new(rng.gen_range(-1.0, 0.5), rng.gen_range(0.2, 1.4), rng.gen_range(1.2, 2.8));
+        let u: Vector3<f64> = 0.4 * vec3(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0)).normalize();
+        let v: Vector3<f64> = 0.4 * u.cross(vec3(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0))).normalize();
+        leaves.push(Box::new(Quad::new(center - 0.5 * (u + v), u, v, green.clone())));
+    }
+    // All leaves share the mask, which is looked up with the texture coordinates of the leaf hit.
+    let leaf: Box<ScalarTexture> = match leaf {
+        Some(leaf) => Box::new(leaf),
+        None => Box::new(LeafShape),
+    };
+    vec.push(Box::new(Cutout::new(BvhNode::build(leaves, t0, t1), leaf)));
+
+    BvhNode::build(vec, t0, t1)
+}
+
+/// Opacity of a simple leaf running along `u`, widest in the middle.
+struct LeafShape;
+
+impl ScalarTexture for LeafShape {
+    fn value(&self, u: f64, v: f64, _p: &Point3<f64>) -> f64 {
+        let half_width = 0.3 * (std::f64::consts::PI * u).sin();
+        if (v - 0.5).abs() < half_width { 1.0 } else { 0.0 }
+    }
+}
+
+/// Room with one-sided walls facing inwards, so the camera looks in through the walls on its
+/// side. A light panel in the ceiling shines down only, a two-sided one stands in the room,
+/// and a paper-thin card is lit from both sides.
+fn room(t0: f64, t1: f64) -> Box<Hitable> {
+    let white: Arc<Material> = Arc::new(Lambertian::new(Box::new(Color::new(0.73, 0.73, 0.73))));
+    let red: Arc<Material> = Arc::new(Lambertian::new(Box::new(Color::new(0.65, 0.05, 0.05))));
+    let green: Arc<Material> = Arc::new(Lambertian::new(Box::new(Color::new(0.12, 0.45, 0.15))));
+    let ceiling_light = Arc::new(DiffuseLight::new(Spd::blackbody(5000.0), 8.0));
+    let standing_light = Arc::new(DiffuseLight::new(Spd::blackbody(3000.0), 4.0).with_two_sided(true));
+
+    let walls: Vec<Quad> = vec![
+        Quad::new(Point3::new(-2.0, 0.0, -2.0), vec3(0.0, 0.0, 4.0), vec3(4.0, 0.0, 0.0), white.clone()),
+        Quad::new(Point3::new(-2.0, 2.5, -2.0), vec3(4.0, 0.0, 0.0), vec3(0.0, 0.0, 4.0), white.clone()),
+        Quad::new(Point3::new(-2.0, 0.0, -2.0), vec3(0.0, 2.5, 0.0), vec3(0.0, 0.0, 4.0), white.clone()),
+        Quad::new(Point3::new(2.0, 0.0, -2.0), vec3(0.0, 0.0, 4.0), vec3(0.0, 2.5, 0.0), white.clone()),
+        Quad::new(Point3::new(-2.0, 0.0, -2.0), vec3(4.0, 0.0, 0.0), vec3(0.0, 2.5, 0.0), red),
+        Quad::new(Point3::new(-2.0, 0.0, 2.0), vec3(0.0, 2.5, 0.0), vec3(4.0, 0.0, 0.0), green),
+    ];
+
+    let mut vec: Vec<Box<Hitable>> = walls.into_iter().map(|wall| Box::new(OneSided::new(Box::new(wall))) as Box<Hitable>).collect();
+    vec.push(Box::new(Quad::new(Point3::new(-0.75, 2.45, -0.75), vec3(1.5, 0.0, 0.0), vec3(0.0, 0.0, 1.5), ceiling_light)));
+    vec.push(Box::new(Quad::new(Point3::new(-1.2, 0.3, -1.5), vec3(0.0, 0.0, 0.6), vec3(0.0, 1.2, 0.0), standing_light)));
+    vec.push(Box::new(Quad::new(Point3::new(0.0, 0.0, 0.3), vec3(0.0, 1.4, 0.0), vec3(0.0, 0.0, 1.0), white.clone())));
+    vec.push(Box::new(Sphere::new(Point3::new(0.5, 0.5, -0.8), 0.5, white)));
+
+    BvhNode::build(vec, t0, t1)
+}
+
+/// A row of spheres with procedural textures: marble, wood, stone cells, cracked mud,
+/// ridged veins, flames, scales and warped agate, over a ground of rough fBm that tiles
+/// every 32 units. The flames and the ground move in sequences of frames. A texture given
+/// on the command line is shown in gray on a single large sphere instead of the row.
+fn procedural(texture: Option<Box<ScalarTexture>>, t0: f64, t1: f64) -> Box<Hitable> {
+    let white = Color::new(0.9, 0.9, 0.88);
+    let marble = ColorRamp::new(vec![(0.0, Color::new(0.2, 0.22, 0.25)), (0.4, Color::new(0.6, 0.6, 0.62)), (1.0, white)]);
+    let wood = ColorRamp::new(vec![(0.0, Color::new(0.55, 0.35, 0.17)), (0.8, Color::new(0.45, 0.27, 0.12)), (1.0, Color::new(0.25, 0.13, 0.05))]);
+    let stone = ColorRamp::new(vec![(0.0, Color::new(0.1, 0.1, 0.1)), (0.5, Color::new(0.5, 0.45, 0.4)), (1.0, white)]);
+    let mud = ColorRamp::new(vec![(0.0, Color::new(0.05, 0.03, 0.02)), (0.08, Color::new(0.35, 0.25, 0.15)), (1.0, Color::new(0.5, 0.38, 0.25))]);
+    let veins = ColorRamp::new(vec![(0.0, Color::new(0.05, 0.1, 0.05)), (1.0, Color::new(0.4, 0.9, 0.5))]);
+    let fire = ColorRamp::new(vec![(0.0, Color::new(0.05, 0.0, 0.0)), (0.3, Color::new(0.8, 0.2, 0.0)), (0.6, Color::new(1.0, 0.8, 0.2)), (1.0, Color::new(1.0, 1.0, 0.9))]);
+    let agate = ColorRamp::new(vec![(0.0, Color::new(0.4, 0.1, 0.05)), (0.5, Color::new(0.9, 0.7, 0.5)), (1.0, Color::new(0.3, 0.3, 0.5))]);
+    let scales = ColorRamp::new(vec![(0.3, Color::new(0.1, 0.3, 0.35)), (1.2, Color::new(0.8, 0.9, 0.7))]);
+    let ground = ColorRamp::new(vec![(0.3, Color::new(0.2, 0.25, 0.1)), (0.7, Color::new(0.45, 0.4, 0.3))]);
+
+    let textures: Vec<Box<Texture>> = vec![
+        Box::new(RampTexture::new(Box::new(Marble::new(4.0, 8.0).with_octaves(Octaves::new(5, 2.0, 0.5)).with_noise(Perlin::new(1))), marble)),
+        Box::new(RampTexture::new(Box::new(Wood::new(6.0, 0.8).with_noise(Perlin::new(2))), wood)),
+        Box::new(RampTexture::new(Box::new(Worley::new(5.0, WorleyFeature::F1).with_seed(3)), stone)),
+        Box::new(RampTexture::new(Box::new(Worley::new(6.0, WorleyFeature::F2MinusF1).with_seed(4)), mud)),
+        Box::new(RampTexture::new(Box::new(Ridged::new(3.0).with_offset(0.9).with_octaves(Octaves::new(6, 2.1, 0.5)).with_noise(Perlin::new(5))), veins)),
+        Box::new(RampTexture::new(Box::new(Turbulence::new(3.0).with_octaves(Octaves::new(5, 2.2, 0.6)).with_noise(Perlin::new(6)).with_time(t0)), fire)),
+        Box::new(RampTexture::new(Box::new(Worley::new(4.0, WorleyFeature::F2).with_seed(7)), scales)),
+        Box::new(RampTexture::new(Box::new(DomainWarp::new(Box::new(Wood::new(8.0, 0.0)), 1.5, 0.4).with_noise(Perlin::new(8))), agate)),
+    ];
+
+    let mut vec: Vec<Box<Hitable>> = vec![];
+    let ground = RampTexture::new(Box::new(Fbm::new(0.5).with_octaves(Octaves::new(8, 2.0, 0.6)).with_noise(Perlin::new(9).with_period(16)).with_time(0.2 * t0)), ground);
+    vec.push(Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, Arc::new(Lambertian::new(Box::new(ground))))));
+    if let Some(texture) = texture {
+        let gray = ColorRamp::new(vec![(0.0, Color::new(0.02, 0.02, 0.02)), (1.0, white)]);
+        vec.push(Box::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, Arc::new(Lambertian::new(Box::new(RampTexture::new(texture, gray)))))));
+        return BvhNode::build(vec, t0, t1);
+    }
+    for (i, texture) in textures.into_iter().enumerate() {
+        let center = Point3::new(0.0, 0.45, 1.05 * i as f64 - 3.675);
+        vec.push(Box::new(Sphere::new(center, 0.45, Arc::new(Lambertian::new(texture)))));
+    }
+
+    BvhNode::build(vec, t0, t1)
+}
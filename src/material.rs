@@ -1,42 +1,154 @@
-use cgmath::{dot, vec3, Vector3};
+use cgmath::{dot, Vector3};
 use cgmath::prelude::*;
 use color::Color;
 use hitable::HitRecord;
+use medium::Medium;
+use microfacet::Ggx;
 use rand::random;
 use ray::Ray;
-use texture::Texture;
+use sampling::{Onb, random_in_unit_sphere, sample_cosine_hemisphere};
+use spectrum;
+use spectrum::{SampledWavelengths, Spd, Spectrum};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use texture::{ScalarTexture, Texture};
+use thinfilm::ThinFilm;
 
 pub trait Material : Sync + Send {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)>;
+
+    /// Surface color used for the albedo pass, without any lighting.
+    fn albedo(&self, rec: &HitRecord) -> Color;
+
+    /// Id of the material for the material id pass, shared by all the primitives using it.
+    fn id(&self) -> usize;
+
+    /// Medium filling the inside of objects made of this material, which rays travel through
+    /// after being transmitted into them.
+    fn interior(&self) -> Option<&Medium> {
+        None
+    }
+
+    /// Light emitted by the surface, for rendering in RGB.
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::black()
+    }
+
+    /// Light emitted at the wavelengths of a path, for rendering spectrally.
+    fn emitted_spectrum(&self, rec: &HitRecord, wavelengths: &SampledWavelengths) -> Spectrum {
+        Spectrum::from_rgb(self.emitted(rec), wavelengths)
+    }
+
+    /// Whether scattering depends on the wavelength, in which case rays hitting the material
+    /// need to carry one.
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+}
+
+static NEXT_MATERIAL_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// Allocates a new id for the material id pass, which materials take when they are made.
+/// Zero is reserved for background.
+pub fn next_material_id() -> usize {
+    NEXT_MATERIAL_ID.fetch_add(1, Ordering::Relaxed)
 }
 
 pub struct Lambertian {
-    albedo: Box<Texture>
+    albedo: Box<Texture>,
+    id: usize,
 }
 
 impl Lambertian {
     pub fn new(albedo: Box<Texture>) -> Lambertian {
-        Lambertian { albedo }
+        Lambertian { albedo, id: next_material_id() }
     }
 }
 
 impl Material for Lambertian {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-        let target = rec.p + rec.normal + random_in_unit_sphere();
-        let scattered = Ray::new(rec.p, target - rec.p, r_in.time);
+        let direction = Onb::from_normal(rec.facing_normal()).to_world(sample_cosine_hemisphere());
+        let scattered = Ray::new(rec.p, direction, r_in.time);
+
+        Some((scattered, self.albedo.value(rec.u, rec.v, &rec.p)))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p)
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+}
+
+/// Rough diffuse surface after Oren and Nayar, made of V-shaped facets whose slopes have a
+/// standard deviation of `sigma` degrees. Unlike Lambertian surfaces they look flatter and
+/// brighter towards the light, like clay or fabric. Zero sigma is Lambertian.
+pub struct OrenNayar {
+    albedo: Box<Texture>,
+    a: f64,
+    b: f64,
+    id: usize,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: Box<Texture>, sigma: f64) -> OrenNayar {
+        let sigma2 = sigma.to_radians().powi(2);
+        OrenNayar {
+            albedo,
+            a: 1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+            id: next_material_id(),
+        }
+    }
+}
+
+impl Material for OrenNayar {
+    /// Samples the cosine weighted hemisphere, so the weight is the BRDF relative to a
+    /// Lambertian one.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        let frame = Onb::from_normal(rec.facing_normal());
+        let wo = frame.to_local(-r_in.direction.normalize());
+        let wi = sample_cosine_hemisphere();
+
+        let sin_o = (1.0 - wo.z * wo.z).max(0.0).sqrt();
+        let sin_i = (1.0 - wi.z * wi.z).max(0.0).sqrt();
+        let cos_phi = if sin_o > 1e-6 && sin_i > 1e-6 {
+            ((wo.x * wi.x + wo.y * wi.y) / (sin_o * sin_i)).max(0.0)
+        } else {
+            0.0
+        };
+        // Sine of the larger and tangent of the smaller of the two angles to the normal.
+        let (sin_alpha, tan_beta) = if wi.z.abs() > wo.z.abs() {
+            (sin_o, sin_i / wi.z.abs())
+        } else {
+            (sin_i, sin_o / wo.z.abs().max(1e-6))
+        };
+
+        let weight = self.a + self.b * cos_phi * sin_alpha * tan_beta;
+        let attenuation = weight * self.albedo.value(rec.u, rec.v, &rec.p);
+        Some((Ray::new(rec.p, frame.to_world(wi), r_in.time), attenuation))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p)
+    }
 
-        Some((scattered, self.albedo.value(0.0, 0.0, &rec.p)))
+    fn id(&self) -> usize {
+        self.id
     }
 }
 
 pub struct Metal {
     albedo: Color,
     fuzz: f64,
+    id: usize,
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Metal {
-        Metal { albedo, fuzz: fuzz.min(1.0) }
+        Metal { albedo, fuzz: fuzz.min(1.0), id: next_material_id() }
     }
 }
 
@@ -45,44 +157,403 @@ impl Material for Metal {
         let reflected = reflect(&r_in.direction.normalize(), &rec.normal);
         let scattered = Ray::new(rec.p, reflected + self.fuzz * random_in_unit_sphere(), r_in.time);
 
-        if dot(scattered.direction, rec.normal) > 0.0 {
+        if dot(scattered.direction, rec.facing_normal()) > 0.0 {
             Some((scattered, self.albedo))
         } else {
             None
         }
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+}
+
+/// Surface that emits light with the given spectrum and does not reflect any.
+pub struct DiffuseLight {
+    spd: Spd,
+    scale: f64,
+    color: Color,
+    two_sided: bool,
+    id: usize,
+}
+
+impl DiffuseLight {
+    /// Light whose emission is scaled to the given luminance. It only emits from the front of
+    /// surfaces, so a light panel is dark from behind. The spectrum has to have some power in
+    /// the visible range to be scaled.
+    pub fn new(spd: Spd, luminance: f64) -> DiffuseLight {
+        let color = spd.to_rgb();
+        assert!(color.luminance() > 0.0, "a light needs a spectrum with visible power");
+        let scale = luminance / color.luminance();
+        DiffuseLight { spd, scale, color: scale * color, two_sided: false, id: next_material_id() }
+    }
+
+    /// Makes the light emit from the back of surfaces as well.
+    pub fn with_two_sided(mut self, two_sided: bool) -> DiffuseLight {
+        self.two_sided = two_sided;
+        self
+    }
+
+    fn emits(&self, rec: &HitRecord) -> bool {
+        rec.front_face || self.two_sided
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<(Ray, Color)> {
+        None
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::white()
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if self.emits(rec) { self.color } else { Color::black() }
+    }
+
+    fn emitted_spectrum(&self, rec: &HitRecord, wavelengths: &SampledWavelengths) -> Spectrum {
+        if !self.emits(rec) {
+            return Spectrum::constant(0.0);
+        }
+        let mut values = wavelengths.lambda;
+        for value in &mut values {
+            *value = self.scale * self.spd.value(*value);
+        }
+        Spectrum { values }
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+}
+
+/// Blend of two materials by a mask, which is zero where only `a` shows and one where only
+/// `b` does. Scattering picks one of the materials at random with the mask as probability,
+/// emission and albedo are blended. A medium can't vary over the surface, so the inside is
+/// filled with the one of `a`, or of `b` if `a` has none.
+pub struct MixMaterial {
+    a: Arc<Material>,
+    b: Arc<Material>,
+    mask: Box<ScalarTexture>,
+    id: usize,
+}
+
+impl MixMaterial {
+    pub fn new(a: Arc<Material>, b: Arc<Material>, mask: Box<ScalarTexture>) -> MixMaterial {
+        MixMaterial { a, b, mask, id: next_material_id() }
+    }
+
+    fn weight(&self, rec: &HitRecord) -> f64 {
+        self.mask.value(rec.u, rec.v, &rec.p).clamp(0.0, 1.0)
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        if random::<f64>() < self.weight(rec) {
+            self.b.scatter(r_in, rec)
+        } else {
+            self.a.scatter(r_in, rec)
+        }
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        let w = self.weight(rec);
+        (1.0 - w) * self.a.albedo(rec) + w * self.b.albedo(rec)
+    }
+
+    fn interior(&self) -> Option<&Medium> {
+        self.a.interior().or_else(|| self.b.interior())
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        let w = self.weight(rec);
+        (1.0 - w) * self.a.emitted(rec) + w * self.b.emitted(rec)
+    }
+
+    fn emitted_spectrum(&self, rec: &HitRecord, wavelengths: &SampledWavelengths) -> Spectrum {
+        let w = self.weight(rec);
+        let mut spectrum = self.a.emitted_spectrum(rec, wavelengths).map(|e| (1.0 - w) * e);
+        spectrum += self.b.emitted_spectrum(rec, wavelengths).map(|e| w * e);
+        spectrum
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.a.is_dispersive() || self.b.is_dispersive()
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+}
+
+/// Complex index of refraction of a metal, per color channel (sampled near 650, 550 and
+/// 450 nm) and relative to air.
+#[derive(Clone, Copy)]
+pub struct ComplexIor {
+    pub eta: Color,
+    pub k: Color,
+}
+
+impl ComplexIor {
+    pub fn gold() -> ComplexIor {
+        ComplexIor { eta: Color::new(0.143, 0.374, 1.442), k: Color::new(3.983, 2.385, 1.603) }
+    }
+
+    pub fn copper() -> ComplexIor {
+        ComplexIor { eta: Color::new(0.200, 0.924, 1.102), k: Color::new(3.912, 2.452, 2.142) }
+    }
+
+    pub fn aluminum() -> ComplexIor {
+        ComplexIor { eta: Color::new(1.657, 0.880, 0.521), k: Color::new(9.224, 6.270, 4.837) }
+    }
+
+    pub fn silver() -> ComplexIor {
+        ComplexIor { eta: Color::new(0.155, 0.117, 0.138), k: Color::new(4.828, 3.122, 2.147) }
+    }
+
+    /// Index of refraction and extinction at a wavelength in nanometers, interpolated between
+    /// the color channels, which are taken to stand for 650, 550 and 450 nm.
+    pub fn at(&self, wavelength: f64) -> (f64, f64) {
+        let t = ((wavelength - 450.0) / 100.0).clamp(0.0, 2.0);
+        let lerp = |c: Color| if t < 1.0 { c.b + t * (c.g - c.b) } else { c.g + (t - 1.0) * (c.r - c.g) };
+        (lerp(self.eta), lerp(self.k))
+    }
+
+    /// Fraction of unpolarized light reflected at the given cosine of the angle of incidence.
+    pub fn fresnel(&self, cosine: f64) -> Color {
+        Color::new(
+            fresnel_conductor(cosine, self.eta.r, self.k.r),
+            fresnel_conductor(cosine, self.eta.g, self.k.g),
+            fresnel_conductor(cosine, self.eta.b, self.k.b),
+        )
+    }
+}
+
+/// Rough metal with a GGX microfacet distribution and the Fresnel reflectance of a conductor.
+/// Anisotropic roughness stretches highlights along the tangent, like brushed metal.
+pub struct Conductor {
+    ior: ComplexIor,
+    distribution: Ggx,
+    film: Option<ThinFilm>,
+    id: usize,
+}
+
+impl Conductor {
+    pub fn new(ior: ComplexIor, roughness: f64) -> Conductor {
+        Conductor::anisotropic(ior, roughness, roughness)
+    }
+
+    /// Roughness `roughness_u` along the tangent and `roughness_v` across it.
+    pub fn anisotropic(ior: ComplexIor, roughness_u: f64, roughness_v: f64) -> Conductor {
+        Conductor { ior, distribution: Ggx::new(roughness_u, roughness_v), film: None, id: next_material_id() }
+    }
+
+    /// Covers the metal with a thin film, like oil or the oxide layer of heat-tinted steel.
+    pub fn with_thin_film(mut self, film: ThinFilm) -> Conductor {
+        self.film = Some(film);
+        self
+    }
+}
+
+impl Material for Conductor {
+    /// Samples a visible microfacet normal and mirrors the ray on it. The weight of such a
+    /// sample is `F * G2 / G1`, so no energy is lost beyond what the Fresnel term absorbs and
+    /// what is shadowed.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        let frame = Onb::from_normal(rec.facing_normal());
+        let wo = frame.to_local(-r_in.direction.normalize());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let (wi, h, weight) = self.distribution.sample_reflection(wo)?;
+        let cosine = wo.dot(h);
+        let fresnel = match self.film {
+            Some(ref film) => {
+                let thickness = film.thickness(rec);
+                let reflectance = |wavelength: f64| {
+                    let (eta, k) = self.ior.at(wavelength);
+                    film.reflectance(thickness, cosine, 1.0, eta, k, wavelength)
+                };
+                match r_in.wavelength {
+                    Some(wavelength) => reflectance(wavelength) * Color::white(),
+                    None => spectrum::reflectance_to_rgb(reflectance),
+                }
+            }
+            None => self.ior.fresnel(cosine),
+        };
+        Some((Ray::new(rec.p, frame.to_world(wi), r_in.time), weight * fresnel))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.ior.fresnel(1.0)
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+}
+
+/// Index of refraction as a function of wavelength.
+#[derive(Debug, Clone, Copy)]
+pub enum Dispersion {
+    /// `n = a + b / λ²`, with `λ` in micrometers.
+    Cauchy { a: f64, b: f64 },
+    /// `n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)`, with `λ` in micrometers.
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// Schott N-BK7 borosilicate crown glass.
+    pub fn bk7() -> Dispersion {
+        Dispersion::Sellmeier {
+            b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+            c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+        }
+    }
+
+    /// Schott K5 crown glass.
+    pub fn crown() -> Dispersion {
+        Dispersion::Cauchy { a: 1.5220, b: 0.00459 }
+    }
+
+    /// Schott SF10 dense flint glass.
+    pub fn flint() -> Dispersion {
+        Dispersion::Cauchy { a: 1.7280, b: 0.01342 }
+    }
+
+    pub fn diamond() -> Dispersion {
+        Dispersion::Sellmeier { b: [0.3306, 4.3356, 0.0], c: [0.030_625, 0.011_236, 0.0] }
+    }
+
+    /// Index of refraction at a wavelength given in nanometers.
+    pub fn index(&self, wavelength: f64) -> f64 {
+        let l = wavelength / 1000.0;
+        let l2 = l * l;
+        match *self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                (1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>()).sqrt()
+            }
+        }
+    }
 }
 
+/// Wavelength at which the index of a dispersive material is used for rays that do not
+/// carry a wavelength (the helium d line).
+const REFERENCE_WAVELENGTH: f64 = 587.6;
+
 pub struct Dielectric {
-    refraction_index: f64
+    refraction_index: f64,
+    dispersion: Option<Dispersion>,
+    interior: Option<Medium>,
+    film: Option<ThinFilm>,
+    id: usize,
 }
 
 impl Dielectric {
     pub fn new(refraction_index: f64) -> Dielectric {
-        Dielectric { refraction_index }
+        Dielectric { refraction_index, dispersion: None, interior: None, film: None, id: next_material_id() }
+    }
+
+    /// Glass whose index of refraction depends on the wavelength, which splits white light
+    /// into its colors.
+    pub fn dispersive(dispersion: Dispersion) -> Dielectric {
+        Dielectric {
+            refraction_index: dispersion.index(REFERENCE_WAVELENGTH),
+            dispersion: Some(dispersion),
+            interior: None,
+            film: None,
+            id: next_material_id(),
+        }
+    }
+
+    /// Makes the inside absorb light, as colored glass or liquids do.
+    pub fn with_absorption(mut self, medium: Medium) -> Dielectric {
+        self.interior = Some(medium);
+        self
+    }
+
+    /// Covers the surface with a thin film, such as an anti-reflective coating. A soap bubble
+    /// is a film on a dielectric with an index of one.
+    pub fn with_thin_film(mut self, film: ThinFilm) -> Dielectric {
+        self.film = Some(film);
+        self
+    }
+
+    /// Reflects or refracts a ray hitting the film. Rays carrying a wavelength pick one with the
+    /// reflectance at that wavelength as probability. Others pick by the average reflectance
+    /// over the colors and are weighted by the color of the reflected or transmitted light.
+    fn scatter_film(&self, film: &ThinFilm, r_in: &Ray, rec: &HitRecord, refracted: Vector3<f64>, exiting: bool) -> (Ray, Color) {
+        let reflected = Ray::new(rec.p, reflect(&r_in.direction, &rec.normal), r_in.time);
+        let refracted = Ray::new(rec.p, refracted, r_in.time);
+        let cosine = r_in.direction.dot(rec.normal).abs() / r_in.direction.magnitude();
+        let thickness = film.thickness(rec);
+        let reflectance = |wavelength: f64| {
+            let refraction_index = match self.dispersion {
+                Some(dispersion) => dispersion.index(wavelength),
+                None => self.refraction_index,
+            };
+            let (outside, inside) = if exiting { (refraction_index, 1.0) } else { (1.0, refraction_index) };
+            film.reflectance(thickness, cosine, outside, inside, 0.0, wavelength)
+        };
+
+        match r_in.wavelength {
+            Some(wavelength) => {
+                if random::<f64>() < reflectance(wavelength) {
+                    (reflected, Color::white())
+                } else {
+                    (refracted, Color::white())
+                }
+            }
+            None => {
+                let color = spectrum::reflectance_to_rgb(reflectance);
+                let probability = ((color.r + color.g + color.b) / 3.0).clamp(1e-4, 1.0 - 1e-4);
+                if random::<f64>() < probability {
+                    (reflected, color / probability)
+                } else {
+                    let transmitted = Color::new(1.0 - color.r, 1.0 - color.g, 1.0 - color.b);
+                    (refracted, transmitted / (1.0 - probability))
+                }
+            }
+        }
     }
 }
 
 impl Material for Dielectric {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
         let attenuation = Color::white();
+        let refraction_index = match (self.dispersion, r_in.wavelength) {
+            (Some(dispersion), Some(wavelength)) => dispersion.index(wavelength),
+            _ => self.refraction_index,
+        };
         let outward_normal: Vector3<f64>;
         let ni_over_nt: f64;
         let cosine: f64;
 
-        if r_in.direction.dot(rec.normal) > 0.0 {
+        if !rec.front_face {
             outward_normal = -rec.normal;
-            ni_over_nt = self.refraction_index;
-            cosine = self.refraction_index * r_in.direction.dot(rec.normal) / r_in.direction.magnitude();
+            ni_over_nt = refraction_index;
+            cosine = refraction_index * r_in.direction.dot(rec.normal) / r_in.direction.magnitude();
         } else {
             outward_normal = rec.normal;
-            ni_over_nt = 1.0 / self.refraction_index;
+            ni_over_nt = 1.0 / refraction_index;
             cosine = -dot(r_in.direction, rec.normal) / r_in.direction.magnitude();
         }
 
 
         if let Some(refracted) = refract(&r_in.direction, &outward_normal, ni_over_nt) {
-            let reflect_prob = schlick(cosine, self.refraction_index);
+            if let Some(ref film) = self.film {
+                return Some(self.scatter_film(film, r_in, rec, refracted, !rec.front_face));
+            }
+            let reflect_prob = schlick(cosine, refraction_index);
 
             if random::<f64>() >= reflect_prob {
                 return Some((Ray::new(rec.p, refracted, r_in.time), attenuation))
@@ -92,15 +563,102 @@ impl Material for Dielectric {
         let reflected = reflect(&r_in.direction, &rec.normal);
         Some((Ray::new(rec.p, reflected, r_in.time), attenuation))
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::white()
+    }
+
+    fn interior(&self) -> Option<&Medium> {
+        self.interior.as_ref()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.dispersion.is_some()
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+}
+
+/// Glass with a rough surface, such as frosted glass, using GGX microfacets for both
+/// reflection and transmission (Walter et al. 2007). The same code handles rays entering and
+/// leaving the object, so paths are treated alike in both directions.
+pub struct RoughDielectric {
+    refraction_index: f64,
+    dispersion: Option<Dispersion>,
+    roughness: Box<ScalarTexture>,
+    interior: Option<Medium>,
+    id: usize,
 }
 
-fn random_in_unit_sphere() -> Vector3<f64> {
-    loop {
-        let v = 2.0 * vec3(random::<f64>(), random::<f64>(), random::<f64>()) - vec3(1.0, 1.0, 1.0);
-        if v.magnitude2() >= 1.0 {
-            return v;
+impl RoughDielectric {
+    pub fn new(refraction_index: f64, roughness: Box<ScalarTexture>) -> RoughDielectric {
+        RoughDielectric { refraction_index, dispersion: None, roughness, interior: None, id: next_material_id() }
+    }
+
+    /// Frosted glass whose index of refraction depends on the wavelength.
+    pub fn dispersive(dispersion: Dispersion, roughness: Box<ScalarTexture>) -> RoughDielectric {
+        RoughDielectric {
+            refraction_index: dispersion.index(REFERENCE_WAVELENGTH),
+            dispersion: Some(dispersion),
+            roughness,
+            interior: None,
+            id: next_material_id(),
         }
     }
+
+    pub fn with_absorption(mut self, medium: Medium) -> RoughDielectric {
+        self.interior = Some(medium);
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: Box<ScalarTexture>) -> RoughDielectric {
+        self.roughness = roughness;
+        self
+    }
+}
+
+impl Material for RoughDielectric {
+    /// Samples a visible microfacet normal, then reflects or refracts on it with the Fresnel
+    /// reflectance as probability. Both cases end up weighted by `G2 / G1`. When refraction
+    /// is impossible the reflectance is one, so total internal reflection needs no special case.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        let roughness = self.roughness.value(rec.u, rec.v, &rec.p);
+        let distribution = Ggx::new(roughness, roughness);
+
+        // Work on the side of the surface the ray comes from; `eta` is the ratio of the index
+        // on the far side to the index on the near side.
+        let refraction_index = match (self.dispersion, r_in.wavelength) {
+            (Some(dispersion), Some(wavelength)) => dispersion.index(wavelength),
+            _ => self.refraction_index,
+        };
+        let eta = if rec.front_face { refraction_index } else { 1.0 / refraction_index };
+        let frame = Onb::from_normal(rec.facing_normal());
+        let wo = frame.to_local(-r_in.direction.normalize());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let (wi, weight) = distribution.sample_dielectric(wo, eta)?;
+        Some((Ray::new(rec.p, frame.to_world(wi), r_in.time), weight * Color::white()))
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::white()
+    }
+
+    fn interior(&self) -> Option<&Medium> {
+        self.interior.as_ref()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.dispersion.is_some()
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
 }
 
 fn reflect(v: &Vector3<f64>, n: &Vector3<f64>) -> Vector3<f64> {
@@ -123,3 +681,24 @@ fn schlick(cosine: f64, ref_idx: f64) -> f64 {
     r0 = r0 * r0;
     r0 + (1.0 - r0) * (1.0 - cosine).powf(5.0)
 }
+
+/// Exact Fresnel reflectance of a conductor with complex index of refraction `eta + ik`.
+fn fresnel_conductor(cosine: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cosine.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos2.sqrt() * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    (rs + rp) / 2.0
+}
@@ -0,0 +1,83 @@
+use cgmath::{vec3, Vector3};
+use color::Color;
+use rand::random;
+use sampling::Onb;
+use std::f64::consts::PI;
+
+/// Homogeneous material filling the inside of an object, which absorbs light according to
+/// the Beer–Lambert law and may scatter it off particles, as milk, wax or skin do.
+#[derive(Clone, Copy)]
+pub struct Medium {
+    /// Absorption coefficient per unit of distance for each color channel.
+    pub absorption: Color,
+    /// Scattering coefficient per unit of distance for each color channel.
+    pub scattering: Color,
+    /// Henyey-Greenstein asymmetry of the scattering, from -1 for scattering back to 1 for
+    /// scattering forward.
+    pub anisotropy: f64,
+}
+
+impl Medium {
+    pub fn new(absorption: Color) -> Medium {
+        Medium { absorption, scattering: Color::black(), anisotropy: 0.0 }
+    }
+
+    /// Medium through which white light takes on `color` after travelling `distance`.
+    pub fn from_color_at_distance(color: Color, distance: f64) -> Medium {
+        let coefficient = |c: f64| -c.max(1e-6).ln() / distance;
+        Medium::new(Color::new(coefficient(color.r), coefficient(color.g), coefficient(color.b)))
+    }
+
+    /// Scattering medium where light travels `mean_free_path` on average between two
+    /// interactions, each of which scatters the fraction `albedo` of it and absorbs the rest.
+    pub fn scattering(albedo: Color, mean_free_path: Color) -> Medium {
+        let extinction = |d: f64| 1.0 / d.max(1e-6);
+        let extinction = Color::new(extinction(mean_free_path.r), extinction(mean_free_path.g), extinction(mean_free_path.b));
+        let scattering = albedo * extinction;
+        Medium {
+            absorption: Color::new(extinction.r - scattering.r, extinction.g - scattering.g, extinction.b - scattering.b),
+            scattering,
+            anisotropy: 0.0,
+        }
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: f64) -> Medium {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    pub fn scatters(&self) -> bool {
+        self.scattering.r > 0.0 || self.scattering.g > 0.0 || self.scattering.b > 0.0
+    }
+
+    /// Rate at which light is either absorbed or scattered.
+    pub fn extinction(&self) -> Color {
+        self.absorption + self.scattering
+    }
+
+    /// Fraction of the light left after travelling `distance` through the medium without
+    /// being absorbed. Scattering is left to the renderer.
+    pub fn transmittance(&self, distance: f64) -> Color {
+        Color::new(
+            (-self.absorption.r * distance).exp(),
+            (-self.absorption.g * distance).exp(),
+            (-self.absorption.b * distance).exp(),
+        )
+    }
+
+    /// Samples the direction light travelling along the unit vector `direction` is scattered
+    /// into, following the Henyey-Greenstein phase function.
+    pub fn sample_direction(&self, direction: Vector3<f64>) -> Vector3<f64> {
+        let g = self.anisotropy;
+        let xi = random::<f64>();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * xi
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+            (1.0 + g * g - s * s) / (2.0 * g)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random::<f64>();
+        Onb::from_normal(direction).to_world(vec3(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta))
+    }
+}
@@ -0,0 +1,202 @@
+use cgmath::{vec3, Vector3};
+use cgmath::prelude::*;
+use color::Color;
+use rand::random;
+use std::f64::consts::PI;
+
+/// GGX (Trowbridge-Reitz) distribution of microfacet normals, with separate roughness along
+/// the tangent and bitangent. All directions are in the local space of an `Onb`.
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+/// Smallest alpha used, below which the distribution degenerates numerically.
+const MIN_ALPHA: f64 = 1e-4;
+
+impl Ggx {
+    /// Distribution for perceptual roughness values in `[0, 1]`, squared to get alpha.
+    pub fn new(roughness_x: f64, roughness_y: f64) -> Ggx {
+        Ggx {
+            alpha_x: (roughness_x * roughness_x).max(MIN_ALPHA),
+            alpha_y: (roughness_y * roughness_y).max(MIN_ALPHA),
+        }
+    }
+
+    /// Distribution with the roughness stretched along the tangent by `anisotropy` in
+    /// `[0, 1]`, the way the Disney BRDF parameterizes it.
+    pub fn anisotropic(roughness: f64, anisotropy: f64) -> Ggx {
+        let aspect = (1.0 - 0.9 * anisotropy).sqrt();
+        let alpha = roughness * roughness;
+        Ggx { alpha_x: (alpha / aspect).max(MIN_ALPHA), alpha_y: (alpha * aspect).max(MIN_ALPHA) }
+    }
+
+    /// Smith's auxiliary function, from which masking and shadowing are derived.
+    pub fn lambda(&self, w: Vector3<f64>) -> f64 {
+        if w.z == 0.0 {
+            return f64::INFINITY;
+        }
+        let a2 = (self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2);
+        (-1.0 + (1.0 + a2 / (w.z * w.z)).sqrt()) / 2.0
+    }
+
+    /// Fraction of the microfacets seen from `w` that are not masked.
+    pub fn g1(&self, w: Vector3<f64>) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Height-correlated masking and shadowing for the pair of directions.
+    pub fn g2(&self, wo: Vector3<f64>, wi: Vector3<f64>) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Samples a microfacet normal visible from `wo` (Heitz 2018), which has to be in the
+    /// upper hemisphere. The distribution of visible normals is proportional to
+    /// `G1(wo) * max(0, wo·h) * D(h) / wo.z`.
+    pub fn sample_visible_normal(&self, wo: Vector3<f64>) -> Vector3<f64> {
+        // Stretch the view direction so the distribution becomes a hemisphere.
+        let vh = vec3(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).normalize();
+
+        let length2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if length2 > 0.0 { vec3(-vh.y, vh.x, 0.0) / length2.sqrt() } else { vec3(1.0, 0.0, 0.0) };
+        let t2 = vh.cross(t1);
+
+        // Sample the projected area of the hemisphere seen from the stretched direction.
+        let r = random::<f64>().sqrt();
+        let phi = 2.0 * PI * random::<f64>();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        vec3(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(0.0)).normalize()
+    }
+
+    /// Mirrors `wo` on a sampled visible microfacet normal. Returns the direction, the
+    /// microfacet normal for evaluating the Fresnel term and the weight `G2 / G1` of the sample,
+    /// or `None` if the reflection points into the surface.
+    pub fn sample_reflection(&self, wo: Vector3<f64>) -> Option<(Vector3<f64>, Vector3<f64>, f64)> {
+        let h = self.sample_visible_normal(wo);
+        let wi = 2.0 * wo.dot(h) * h - wo;
+        if wi.z <= 0.0 {
+            return None;
+        }
+        Some((wi, h, self.g2(wo, wi) / self.g1(wo)))
+    }
+
+    /// Reflects or refracts `wo` on a sampled visible microfacet normal of an interface where
+    /// `eta` is the ratio of the index of refraction below the surface to the one above it,
+    /// picking reflection with the Fresnel reflectance as probability (Walter et al. 2007).
+    /// When refraction is impossible the reflectance is one, so total internal reflection
+    /// needs no special case. Returns the direction and the weight `G2 / G1` of the sample.
+    pub fn sample_dielectric(&self, wo: Vector3<f64>, eta: f64) -> Option<(Vector3<f64>, f64)> {
+        let h = self.sample_visible_normal(wo);
+        let cos_o = wo.dot(h);
+
+        let wi = if random::<f64>() < fresnel_dielectric(cos_o, eta) {
+            let wi = 2.0 * cos_o * h - wo;
+            if wi.z <= 0.0 {
+                return None;
+            }
+            wi
+        } else {
+            let cos_t = (1.0 - (1.0 - cos_o * cos_o) / (eta * eta)).sqrt();
+            let wi = -wo / eta + (cos_o / eta - cos_t) * h;
+            if wi.z >= 0.0 {
+                return None;
+            }
+            wi
+        };
+
+        Some((wi, self.g2(wo, wi) / self.g1(wo)))
+    }
+}
+
+/// Exact Fresnel reflectance of unpolarized light hitting a dielectric interface, where `eta`
+/// is the ratio of the index of refraction behind the interface to the one in front of it.
+pub fn fresnel_dielectric(cosine: f64, eta: f64) -> f64 {
+    let sin2_t = (1.0 - cosine * cosine) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cosine - eta * cos_t) / (cosine + eta * cos_t);
+    let rp = (eta * cosine - cos_t) / (eta * cosine + cos_t);
+    (rs * rs + rp * rp) / 2.0
+}
+
+/// Schlick's approximation of the Fresnel reflectance for reflectance `f0` at normal incidence.
+pub fn schlick_fresnel(f0: Color, cosine: f64) -> Color {
+    let weight = (1.0 - cosine).clamp(0.0, 1.0).powi(5);
+    (1.0 - weight) * f0 + weight * Color::white()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sampling::tests::chi_square_test;
+
+    /// GGX distribution of microfacet normals, normalized over projected solid angle.
+    fn d(ggx: &Ggx, h: Vector3<f64>) -> f64 {
+        if h.z <= 0.0 {
+            return 0.0;
+        }
+        let e = (h.x / ggx.alpha_x).powi(2) + (h.y / ggx.alpha_y).powi(2) + h.z * h.z;
+        1.0 / (PI * ggx.alpha_x * ggx.alpha_y * e * e)
+    }
+
+    fn check_visible_normals(ggx: Ggx, wo: Vector3<f64>) {
+        const SAMPLES: usize = 1_000_000;
+        let (theta_bins, phi_bins) = (16, 16);
+        let bin_of = |h: Vector3<f64>| {
+            let theta = h.z.clamp(-1.0, 1.0).acos();
+            let phi = h.y.atan2(h.x) + PI;
+            let t = ((theta / (PI / 2.0) * theta_bins as f64) as usize).min(theta_bins - 1);
+            let p = ((phi / (2.0 * PI) * phi_bins as f64) as usize).min(phi_bins - 1);
+            t * phi_bins + p
+        };
+
+        let mut observed = vec![0; theta_bins * phi_bins];
+        for _ in 0..SAMPLES {
+            let h = ggx.sample_visible_normal(wo);
+            assert!((h.magnitude() - 1.0).abs() < 1e-9 && h.z >= 0.0);
+            observed[bin_of(h)] += 1;
+        }
+
+        // Integrate G1(wo) max(0, wo·h) D(h) / wo.z over every bin with the midpoint rule.
+        let steps = 32;
+        let mut expected = vec![0.0; theta_bins * phi_bins];
+        let (d_theta, d_phi) = (PI / 2.0 / theta_bins as f64, 2.0 * PI / phi_bins as f64);
+        for t in 0..theta_bins {
+            for p in 0..phi_bins {
+                let mut integral = 0.0;
+                for i in 0..steps {
+                    for j in 0..steps {
+                        let theta = (t as f64 + (i as f64 + 0.5) / steps as f64) * d_theta;
+                        let phi = (p as f64 + (j as f64 + 0.5) / steps as f64) * d_phi - PI;
+                        let h = vec3(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+                        let density = ggx.g1(wo) * wo.dot(h).max(0.0) * d(&ggx, h) / wo.z;
+                        integral += density * theta.sin();
+                    }
+                }
+                expected[t * phi_bins + p] = integral * d_theta * d_phi / (steps * steps) as f64 * SAMPLES as f64;
+            }
+        }
+
+        let total: f64 = expected.iter().sum();
+        assert!((total / SAMPLES as f64 - 1.0).abs() < 1e-2, "visible normals integrate to {}", total / SAMPLES as f64);
+        chi_square_test(&observed, &expected);
+    }
+
+    #[test]
+    fn visible_normals_isotropic() {
+        check_visible_normals(Ggx::new(0.7, 0.7), vec3(0.6, 0.0, 0.8));
+    }
+
+    #[test]
+    fn visible_normals_anisotropic_grazing() {
+        check_visible_normals(Ggx::new(0.9, 0.6), vec3(0.5, 0.7, 0.2).normalize());
+    }
+}
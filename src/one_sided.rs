@@ -0,0 +1,33 @@
+use aabb::AABB;
+use hitable::{Hitable, HitRecord};
+use ray::Ray;
+
+/// Makes any hitable one-sided: rays hitting the back of its surfaces pass through as if it
+/// was not there, which is backface culling. Useful for walls that should not block a camera
+/// looking in from outside, or meshes that are open at the back.
+pub struct OneSided {
+    hitable: Box<Hitable>,
+}
+
+impl OneSided {
+    pub fn new(hitable: Box<Hitable>) -> OneSided {
+        OneSided { hitable }
+    }
+}
+
+impl Hitable for OneSided {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut t_min = t_min;
+        loop {
+            let rec = self.hitable.hit(ray, t_min, t_max)?;
+            if rec.front_face {
+                return Some(rec);
+            }
+            t_min = rec.t;
+        }
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<AABB> {
+        self.hitable.bounding_box(t0, t1)
+    }
+}
@@ -0,0 +1,301 @@
+use camera::ShutterCurve;
+use film::{AOV_CHANNELS, Channel};
+use lens::LensDistortion;
+use panorama::{FisheyeMapping, StereoLayout};
+use physical::{PhysicalCameraSettings, SensorFormat};
+use procedural;
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+/// Projection used by the camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+    Fisheye(FisheyeMapping),
+    Equirectangular,
+    CubeMap,
+    OmnidirectionalStereo(StereoLayout),
+}
+
+/// Built-in scenes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SceneName {
+    TwoPerlinSpheres,
+    Random,
+    Turntable,
+    Glass,
+    Materials,
+    Mix,
+    Translucent,
+    ThinFilm,
+    Bumps,
+    Cutout,
+    Room,
+    Procedural,
+}
+
+/// Command line options of the renderer.
+pub struct Options {
+    pub output: PathBuf,
+    pub scene: SceneName,
+    pub mask: Option<PathBuf>,
+    pub normal_map: Option<PathBuf>,
+    pub texture: Option<String>,
+    pub frames: Option<(u32, u32)>,
+    pub fps: f64,
+    pub shutter_angle: f64,
+    pub shutter_curve: ShutterCurve,
+    pub rolling_shutter: f64,
+    pub width: u32,
+    pub height: u32,
+    pub projection: Projection,
+    pub ipd: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+    pub aperture_blades: Option<u32>,
+    pub blade_rotation: f64,
+    pub aperture_mask: Option<PathBuf>,
+    pub cat_eye: f64,
+    pub tilt: (f64, f64),
+    pub shift: (f64, f64),
+    pub distortion: LensDistortion,
+    pub physical: Option<PhysicalCameraSettings>,
+    pub autofocus: Option<(f64, f64)>,
+    pub aovs: Vec<Channel>,
+    pub samples: u32,
+    pub denoise: bool,
+    pub spectral: bool,
+}
+
+impl Options {
+    pub fn from_args() -> Options {
+        let mut options = Options {
+            output: PathBuf::from("images/output.png"),
+            scene: SceneName::TwoPerlinSpheres,
+            mask: None,
+            normal_map: None,
+            texture: None,
+            frames: None,
+            fps: 24.0,
+            shutter_angle: 180.0,
+            shutter_curve: ShutterCurve::Box,
+            rolling_shutter: 0.0,
+            width: 600,
+            height: 400,
+            projection: Projection::Perspective,
+            ipd: 0.064,
+            aperture: 0.0,
+            focus_dist: 10.0,
+            aperture_blades: None,
+            blade_rotation: 0.0,
+            aperture_mask: None,
+            cat_eye: 0.0,
+            tilt: (0.0, 0.0),
+            shift: (0.0, 0.0),
+            distortion: LensDistortion::default(),
+            physical: None,
+            autofocus: None,
+            aovs: Vec::new(),
+            samples: 50,
+            denoise: false,
+            spectral: false,
+        };
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--output" => options.output = PathBuf::from(required_value(&arg, args.next())),
+                "--scene" => options.scene = parse_scene(&required_value(&arg, args.next())),
+                "--mask" => options.mask = Some(PathBuf::from(required_value(&arg, args.next()))),
+                "--normal-map" => options.normal_map = Some(PathBuf::from(required_value(&arg, args.next()))),
+                "--texture" => options.texture = Some(parse_texture(&required_value(&arg, args.next()))),
+                "--frames" => options.frames = Some(parse_frames(&arg, &required_value(&arg, args.next()))),
+                "--fps" => options.fps = parse_float(&arg, &required_value(&arg, args.next())),
+                "--shutter-angle" => options.shutter_angle = parse_float(&arg, &required_value(&arg, args.next())),
+                "--shutter-curve" => options.shutter_curve = parse_shutter_curve(&arg, &required_value(&arg, args.next())),
+                "--rolling-shutter" => options.rolling_shutter = parse_readout(&arg, &required_value(&arg, args.next())),
+                "--width" => options.width = parse_number(&arg, &required_value(&arg, args.next())),
+                "--height" => options.height = parse_number(&arg, &required_value(&arg, args.next())),
+                "--camera" => options.projection = parse_projection(&required_value(&arg, args.next())),
+                "--ipd" => options.ipd = parse_float(&arg, &required_value(&arg, args.next())),
+                "--aperture" => options.aperture = parse_float(&arg, &required_value(&arg, args.next())),
+                "--focus-dist" => options.focus_dist = parse_float(&arg, &required_value(&arg, args.next())),
+                "--blades" => options.aperture_blades = Some(parse_number(&arg, &required_value(&arg, args.next()))),
+                "--blade-rotation" => options.blade_rotation = parse_float(&arg, &required_value(&arg, args.next())),
+                "--aperture-mask" => options.aperture_mask = Some(PathBuf::from(required_value(&arg, args.next()))),
+                "--cat-eye" => options.cat_eye = parse_float(&arg, &required_value(&arg, args.next())),
+                "--tilt" => options.tilt = parse_pair(&arg, &required_value(&arg, args.next())),
+                "--shift" => options.shift = parse_pair(&arg, &required_value(&arg, args.next())),
+                "--distortion" => options.distortion = parse_distortion(&arg, &required_value(&arg, args.next())),
+                "--focal-length" => physical(&mut options).focal_length = parse_float(&arg, &required_value(&arg, args.next())),
+                "--sensor" => physical(&mut options).sensor = parse_sensor(&required_value(&arg, args.next())),
+                "--f-stop" => physical(&mut options).f_number = parse_float(&arg, &required_value(&arg, args.next())),
+                "--shutter" => physical(&mut options).shutter_duration = parse_float(&arg, &required_value(&arg, args.next())),
+                "--iso" => physical(&mut options).iso = parse_float(&arg, &required_value(&arg, args.next())),
+                "--autofocus" => options.autofocus = Some(parse_pair(&arg, &required_value(&arg, args.next()))),
+                "--aovs" => options.aovs = parse_channels(&required_value(&arg, args.next())),
+                "--samples" => options.samples = parse_number(&arg, &required_value(&arg, args.next())),
+                "--denoise" => options.denoise = true,
+                "--spectral" => options.spectral = true,
+                _ => usage(&format!("unknown option '{}'", arg)),
+            }
+        }
+
+        options
+    }
+}
+
+fn parse_scene(value: &str) -> SceneName {
+    match value {
+        "perlin" => SceneName::TwoPerlinSpheres,
+        "random" => SceneName::Random,
+        "turntable" => SceneName::Turntable,
+        "glass" => SceneName::Glass,
+        "materials" => SceneName::Materials,
+        "mix" => SceneName::Mix,
+        "translucent" => SceneName::Translucent,
+        "thinfilm" => SceneName::ThinFilm,
+        "bumps" => SceneName::Bumps,
+        "cutout" => SceneName::Cutout,
+        "room" => SceneName::Room,
+        "procedural" => SceneName::Procedural,
+        _ => usage(&format!("unknown scene '{}'", value)),
+    }
+}
+
+/// Checks a description of a procedural texture, which is built anew for every frame.
+fn parse_texture(value: &str) -> String {
+    if let Err(error) = procedural::parse_texture(value) {
+        usage(&error);
+    }
+    value.to_string()
+}
+
+/// Parses an inclusive range of frames `FIRST-LAST`, or a single frame.
+fn parse_frames(option: &str, value: &str) -> (u32, u32) {
+    let mut parts = value.splitn(2, '-');
+    let first = parse_number(option, parts.next().unwrap_or(""));
+    let last = parts.next().map_or(first, |last| parse_number(option, last));
+    if last < first {
+        usage(&format!("empty frame range '{}'", value));
+    }
+    (first, last)
+}
+
+/// Parses `box`, `trapezoid:OPEN,CLOSE` or `custom:V1,V2,...`.
+fn parse_shutter_curve(option: &str, value: &str) -> ShutterCurve {
+    let mut parts = value.splitn(2, ':');
+    match (parts.next().unwrap_or(""), parts.next()) {
+        ("box", None) => ShutterCurve::Box,
+        ("trapezoid", Some(values)) => match parse_pair(option, values) {
+            (open, close) if open >= 0.0 && close >= 0.0 && open + close <= 1.0 => ShutterCurve::Trapezoid { open, close },
+            _ => usage(&format!("trapezoid ramps must add up to at most 1 for {}", option)),
+        },
+        ("custom", Some(values)) => {
+            let values = parse_floats(option, values);
+            if values.iter().any(|&v| v < 0.0) || !values.iter().any(|&v| v > 0.0) {
+                usage(&format!("custom shutter curve must be non-negative and open at some point for {}", option));
+            }
+            ShutterCurve::Custom(values)
+        }
+        _ => usage(&format!("unknown shutter curve '{}'", value)),
+    }
+}
+
+fn physical(options: &mut Options) -> &mut PhysicalCameraSettings {
+    options.physical.get_or_insert_with(PhysicalCameraSettings::default)
+}
+
+fn parse_sensor(value: &str) -> SensorFormat {
+    SensorFormat::parse(value).unwrap_or_else(|| usage(&format!("unknown sensor '{}'", value)))
+}
+
+fn parse_projection(value: &str) -> Projection {
+    match value {
+        "perspective" => Projection::Perspective,
+        "orthographic" => Projection::Orthographic,
+        "fisheye" => Projection::Fisheye(FisheyeMapping::Equidistant),
+        "fisheye-equisolid" => Projection::Fisheye(FisheyeMapping::Equisolid),
+        "equirectangular" => Projection::Equirectangular,
+        "cubemap" => Projection::CubeMap,
+        "ods-side-by-side" => Projection::OmnidirectionalStereo(StereoLayout::SideBySide),
+        "ods-over-under" => Projection::OmnidirectionalStereo(StereoLayout::OverUnder),
+        _ => usage(&format!("unknown camera '{}'", value)),
+    }
+}
+
+fn parse_channels(value: &str) -> Vec<Channel> {
+    if value == "all" {
+        return AOV_CHANNELS.to_vec();
+    }
+
+    value.split(',')
+        .map(|name| Channel::parse(name).unwrap_or_else(|| usage(&format!("unknown channel '{}'", name))))
+        .collect()
+}
+
+fn parse_number(option: &str, value: &str) -> u32 {
+    value.parse().unwrap_or_else(|_| usage(&format!("invalid value '{}' for {}", value, option)))
+}
+
+fn parse_float(option: &str, value: &str) -> f64 {
+    value.parse().unwrap_or_else(|_| usage(&format!("invalid value '{}' for {}", value, option)))
+}
+
+/// Parses the readout time of a rolling shutter. The top row is read out first, so it can't
+/// be negative.
+fn parse_readout(option: &str, value: &str) -> f64 {
+    let readout = parse_float(option, value);
+    if readout.is_nan() || readout < 0.0 {
+        usage(&format!("readout time for {} must not be negative", option));
+    }
+    readout
+}
+
+/// Parses `A,B`, where `B` defaults to zero.
+fn parse_pair(option: &str, value: &str) -> (f64, f64) {
+    let values = parse_floats(option, value);
+    match values.len() {
+        1 => (values[0], 0.0),
+        2 => (values[0], values[1]),
+        _ => usage(&format!("expected one or two values for {}", option)),
+    }
+}
+
+/// Parses `K1,K2,K3[,P1,P2]`.
+fn parse_distortion(option: &str, value: &str) -> LensDistortion {
+    let values = parse_floats(option, value);
+    match values.len() {
+        3 => LensDistortion::radial(values[0], values[1], values[2]),
+        5 => LensDistortion { k1: values[0], k2: values[1], k3: values[2], p1: values[3], p2: values[4] },
+        _ => usage(&format!("expected three or five values for {}", option)),
+    }
+}
+
+fn parse_floats(option: &str, value: &str) -> Vec<f64> {
+    value.split(',').map(|v| parse_float(option, v)).collect()
+}
+
+fn required_value(option: &str, value: Option<String>) -> String {
+    value.unwrap_or_else(|| usage(&format!("missing value for {}", option)))
+}
+
+fn usage(error: &str) -> ! {
+    eprintln!("error: {}", error);
+    eprintln!("usage: weekend-raytracer [--output FILE] [--width N] [--height N] [--samples N] [--denoise] [--spectral]");
+    eprintln!("                         [--scene perlin|random|turntable|glass|materials|mix|translucent|thinfilm|bumps|cutout|room|procedural] [--mask FILE] [--normal-map FILE] [--texture TEXTURE] [--frames FIRST-LAST] [--fps N] [--shutter-angle DEGREES]");
+    eprintln!("                         [--shutter-curve box|trapezoid:OPEN,CLOSE|custom:V1,V2,...] [--rolling-shutter READOUT]");
+    eprintln!("                         [--camera PROJECTION] [--ipd DISTANCE] [--aovs all|CHANNEL,...]");
+    eprintln!("                         [--aperture DIAMETER] [--focus-dist DISTANCE] [--blades N] [--blade-rotation DEGREES]");
+    eprintln!("                         [--aperture-mask FILE] [--cat-eye AMOUNT] [--tilt TILT[,SWING]] [--shift X[,Y]]");
+    eprintln!("                         [--distortion K1,K2,K3[,P1,P2]] [--autofocus X,Y]");
+    eprintln!("                         [--focal-length MM] [--sensor SENSOR] [--f-stop N] [--shutter SECONDS] [--iso N]");
+    eprintln!("sensors: full-frame, aps-c, micro-four-thirds, WIDTHxHEIGHT (in mm)");
+    eprintln!("projections: perspective, orthographic, fisheye, fisheye-equisolid, equirectangular, cubemap,");
+    eprintln!("             ods-side-by-side, ods-over-under");
+    eprintln!("textures: NAME[:KEY=VALUE,...], see procedural::parse_texture, e.g. marble:scale=4,distortion=8");
+    eprintln!("channels: {}", AOV_CHANNELS.iter().map(|c| c.name()).collect::<Vec<_>>().join(", "));
+    process::exit(1);
+}
@@ -0,0 +1,192 @@
+use camera::{Camera, Frame, Shutter};
+use cgmath::{Point3, vec3, Vector3};
+use ray::Ray;
+use std::f64::consts::PI;
+
+/// How the angle from the optical axis maps to the distance from the center of a fisheye image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FisheyeMapping {
+    /// Distance is proportional to the angle, `r = f θ`.
+    Equidistant,
+    /// Preserves solid angle, `r = 2 f sin(θ / 2)`.
+    Equisolid,
+}
+
+/// Circular fisheye whose image circle touches the top and bottom of the film. Film outside
+/// of the image circle stays black.
+pub struct FisheyeCamera {
+    frame: Frame,
+    half_fov: f64,
+    aspect: f64,
+    mapping: FisheyeMapping,
+    shutter: Shutter,
+}
+
+impl FisheyeCamera {
+    pub fn new(lookfrom: Point3<f64>, lookat: Point3<f64>, vup: Vector3<f64>, fov: f64, aspect: f64, mapping: FisheyeMapping, shutter: Shutter) -> FisheyeCamera {
+        FisheyeCamera {
+            frame: Frame::look_at(lookfrom, lookat, vup),
+            half_fov: fov.to_radians() / 2.0,
+            aspect,
+            mapping,
+            shutter,
+        }
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let x = (2.0 * s - 1.0) * self.aspect;
+        let y = 2.0 * t - 1.0;
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => r * self.half_fov,
+            FisheyeMapping::Equisolid => 2.0 * (r * (self.half_fov / 2.0).sin()).asin(),
+        };
+        let phi = y.atan2(x);
+
+        let direction = vec3(theta.sin() * phi.cos(), theta.sin() * phi.sin(), -theta.cos());
+        Some(Ray::new(self.frame.origin, self.frame.to_world(direction), self.shutter.sample_time(t)))
+    }
+
+    fn depth(&self, p: Point3<f64>) -> f64 {
+        self.frame.distance(p)
+    }
+}
+
+/// Latitude-longitude panorama covering the full sphere. The center of the film looks
+/// towards `lookat` and the film should have an aspect ratio of 2:1.
+pub struct EquirectangularCamera {
+    frame: Frame,
+    shutter: Shutter,
+}
+
+impl EquirectangularCamera {
+    pub fn new(lookfrom: Point3<f64>, lookat: Point3<f64>, vup: Vector3<f64>, shutter: Shutter) -> EquirectangularCamera {
+        EquirectangularCamera {
+            frame: Frame::look_at(lookfrom, lookat, vup),
+            shutter,
+        }
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        Some(Ray::new(self.frame.origin, self.frame.to_world(equirectangular_direction(s, t)), self.shutter.sample_time(t)))
+    }
+
+    fn depth(&self, p: Point3<f64>) -> f64 {
+        self.frame.distance(p)
+    }
+}
+
+/// Direction in camera space for the given film coordinates of a latitude-longitude panorama.
+pub fn equirectangular_direction(s: f64, t: f64) -> Vector3<f64> {
+    let longitude = (s - 0.5) * 2.0 * PI;
+    let latitude = (t - 0.5) * PI;
+    vec3(latitude.cos() * longitude.sin(), latitude.sin(), -latitude.cos() * longitude.cos())
+}
+
+/// How the two eyes of a stereo pair are packed into one film.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StereoLayout {
+    /// Left eye in the left half, right eye in the right half.
+    SideBySide,
+    /// Left eye in the top half, right eye in the bottom half.
+    OverUnder,
+}
+
+/// Omnidirectional stereo: a latitude-longitude panorama for each eye, where every ray
+/// starts from a viewing circle of diameter `ipd` so that the eyes are offset sideways in
+/// whichever direction the viewer is looking.
+pub struct OdsCamera {
+    frame: Frame,
+    ipd: f64,
+    layout: StereoLayout,
+    shutter: Shutter,
+}
+
+impl OdsCamera {
+    pub fn new(lookfrom: Point3<f64>, lookat: Point3<f64>, vup: Vector3<f64>, ipd: f64, layout: StereoLayout, shutter: Shutter) -> OdsCamera {
+        OdsCamera {
+            frame: Frame::look_at(lookfrom, lookat, vup),
+            ipd,
+            layout,
+            shutter,
+        }
+    }
+}
+
+impl Camera for OdsCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let time = self.shutter.sample_time(t);
+        let (left_eye, s, t) = match self.layout {
+            StereoLayout::SideBySide if s < 0.5 => (true, 2.0 * s, t),
+            StereoLayout::SideBySide => (false, 2.0 * s - 1.0, t),
+            StereoLayout::OverUnder if t >= 0.5 => (true, s, 2.0 * t - 1.0),
+            StereoLayout::OverUnder => (false, s, 2.0 * t),
+        };
+
+        let longitude = (s - 0.5) * 2.0 * PI;
+        let side = if left_eye { -0.5 } else { 0.5 };
+        let offset = side * self.ipd * vec3(longitude.cos(), 0.0, longitude.sin());
+
+        Some(Ray::new(
+            self.frame.origin + self.frame.to_world(offset),
+            self.frame.to_world(equirectangular_direction(s, t)),
+            time,
+        ))
+    }
+
+    fn depth(&self, p: Point3<f64>) -> f64 {
+        self.frame.distance(p)
+    }
+}
+
+/// Renders all six faces of a cube map into one film laid out as a 3x2 grid:
+/// `+X -X +Y` on the top row and `-Y +Z -Z` on the bottom row, using the OpenGL
+/// face orientations in camera space. The film should have an aspect ratio of 3:2.
+pub struct CubeMapCamera {
+    frame: Frame,
+    shutter: Shutter,
+}
+
+/// Forward, right and up vectors of each face, in layout order.
+const CUBE_FACES: [([f64; 3], [f64; 3], [f64; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, 0.0, 1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+];
+
+impl CubeMapCamera {
+    pub fn new(lookfrom: Point3<f64>, lookat: Point3<f64>, vup: Vector3<f64>, shutter: Shutter) -> CubeMapCamera {
+        CubeMapCamera {
+            frame: Frame::look_at(lookfrom, lookat, vup),
+            shutter,
+        }
+    }
+}
+
+impl Camera for CubeMapCamera {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let column = ((s * 3.0) as usize).min(2);
+        let row = (((1.0 - t) * 2.0) as usize).min(1);
+        let a = 2.0 * (s * 3.0 - column as f64) - 1.0;
+        let b = 2.0 * (t * 2.0 - (1 - row) as f64) - 1.0;
+
+        let (forward, right, up) = CUBE_FACES[row * 3 + column];
+        let direction = Vector3::from(forward) + a * Vector3::from(right) + b * Vector3::from(up);
+        Some(Ray::new(self.frame.origin, self.frame.to_world(direction), self.shutter.sample_time(t)))
+    }
+
+    fn depth(&self, p: Point3<f64>) -> f64 {
+        self.frame.distance(p)
+    }
+}
@@ -1,58 +1,159 @@
 use array_init::array_init;
-use cgmath::{Point3, prelude::*, vec3, Vector3};
+use cgmath::{Point3, prelude::*, vec3, vec4, Vector3, Vector4};
 use color::Color;
-use rand::{random, Rng, thread_rng};
-use texture::Texture;
+use rand::{Rng, SeedableRng, XorShiftRng};
+use texture::{ScalarTexture, Texture};
 
+/// Settings for sums of noise at increasing frequencies: the number of octaves, the factor by
+/// which the frequency grows from one to the next (lacunarity) and the factor by which the
+/// amplitude shrinks (gain).
+#[derive(Debug, Clone, Copy)]
+pub struct Octaves {
+    pub count: u32,
+    pub lacunarity: f64,
+    pub gain: f64,
+}
+
+impl Octaves {
+    pub fn new(count: u32, lacunarity: f64, gain: f64) -> Octaves {
+        Octaves { count, lacunarity, gain }
+    }
+}
+
+impl Default for Octaves {
+    fn default() -> Octaves {
+        Octaves::new(7, 2.0, 0.5)
+    }
+}
+
+/// Gradient noise after Ken Perlin, roughly in `[-1, 1]`. The same seed always gives the
+/// same noise. With a period the noise repeats every that many units along every axis, for
+/// textures that have to tile.
 pub struct Perlin {
     vectors: [Vector3<f64>; 256],
+    vectors4: [Vector4<f64>; 256],
     perm_x: Permutation,
     perm_y: Permutation,
     perm_z: Permutation,
+    perm_w: Permutation,
+    period: Option<i64>,
 }
 
 impl Perlin {
-    fn new() -> Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut rng = seeded_rng(seed);
+        let vectors = array_init(|_| vec3(random_around_zero(&mut rng), random_around_zero(&mut rng), random_around_zero(&mut rng)).normalize());
+        let vectors4 = array_init(|_| {
+            vec4(random_around_zero(&mut rng), random_around_zero(&mut rng), random_around_zero(&mut rng), random_around_zero(&mut rng)).normalize()
+        });
         Perlin {
-            vectors: array_init(|_| { vec3(random_around_zero(), random_around_zero(), random_around_zero()).normalize() }),
-            perm_x: Permutation::new(),
-            perm_y: Permutation::new(),
-            perm_z: Permutation::new(),
+            vectors,
+            vectors4,
+            perm_x: Permutation::new(&mut rng),
+            perm_y: Permutation::new(&mut rng),
+            perm_z: Permutation::new(&mut rng),
+            perm_w: Permutation::new(&mut rng),
+            period: None,
         }
     }
 
-    fn noise(&self, p: Vector3<f64>) -> f64 {
-        let u = fraction(p.x);
-        let v = fraction(p.y);
-        let w = fraction(p.z);
+    /// Makes the noise repeat every `period` units, also in sums of octaves.
+    pub fn with_period(mut self, period: u32) -> Perlin {
+        assert!(period > 0, "the period of noise must be positive");
+        self.period = Some(period as i64);
+        self
+    }
+
+    pub fn noise(&self, p: Vector3<f64>) -> f64 {
+        self.noise_with_period(p, self.period)
+    }
 
-        let (x0, x1) = self.perm_x.lookup(p.x);
-        let (y0, y1) = self.perm_y.lookup(p.y);
-        let (z0, z1) = self.perm_z.lookup(p.z);
+    fn noise_with_period(&self, p: Vector3<f64>, period: Option<i64>) -> f64 {
+        let (x0, x1) = self.perm_x.lookup(p.x, period);
+        let (y0, y1) = self.perm_y.lookup(p.y, period);
+        let (z0, z1) = self.perm_z.lookup(p.z, period);
 
         let c = [
             [[self.vectors[x0 ^ y0 ^ z0], self.vectors[x0 ^ y0 ^ z1]], [self.vectors[x0 ^ y1 ^ z0], self.vectors[x0 ^ y1 ^ z1]]],
             [[self.vectors[x1 ^ y0 ^ z0], self.vectors[x1 ^ y0 ^ z1]], [self.vectors[x1 ^ y1 ^ z0], self.vectors[x1 ^ y1 ^ z1]]]
         ];
 
-        perlin_interp(&c, u, v, w)
+        perlin_interp(&c, fraction(p.x), fraction(p.y), fraction(p.z))
+    }
+
+    /// Noise in four dimensions, the fourth being time. The period only tiles space, the
+    /// noise doesn't loop in time.
+    fn noise4_with_period(&self, p: Vector3<f64>, w: f64, period: Option<i64>) -> f64 {
+        let x = self.perm_x.lookup(p.x, period);
+        let y = self.perm_y.lookup(p.y, period);
+        let z = self.perm_z.lookup(p.z, period);
+        let t = self.perm_w.lookup(w, None);
+        let corner = |i: usize, c: (usize, usize)| if i == 0 { c.0 } else { c.1 };
+
+        let f = vec4(fraction(p.x), fraction(p.y), fraction(p.z), fraction(w));
+        let s = vec4(hermite_cubic(f.x), hermite_cubic(f.y), hermite_cubic(f.z), hermite_cubic(f.w));
+        let mut sum = 0.0;
+        for index in 0..16 {
+            let (i, j, k, l) = (index & 1, (index >> 1) & 1, (index >> 2) & 1, (index >> 3) & 1);
+            let gradient = self.vectors4[corner(i, x) ^ corner(j, y) ^ corner(k, z) ^ corner(l, t)];
+            let offset = f - vec4(i as f64, j as f64, k as f64, l as f64);
+            sum += blend(i, s.x) * blend(j, s.y) * blend(k, s.z) * blend(l, s.w) * gradient.dot(offset);
+        }
+        sum
     }
 
     fn turb(&self, p: Vector3<f64>) -> f64 {
-        self.turb_depth(p, 7)
+        self.fbm(p, Octaves::default()).abs()
     }
 
-    fn turb_depth(&self, mut p: Vector3<f64>, depth: u32) -> f64 {
+    /// Fractional Brownian motion: octaves of noise added up, unnormalized.
+    pub fn fbm(&self, p: Vector3<f64>, octaves: Octaves) -> f64 {
+        self.sum_octaves(octaves, |frequency, period| self.noise_with_period(frequency * p, period))
+    }
+
+    /// Fractional Brownian motion of noise in four dimensions, which changes smoothly with
+    /// the time `w`.
+    pub fn fbm4(&self, p: Vector3<f64>, w: f64, octaves: Octaves) -> f64 {
+        self.sum_octaves(octaves, |frequency, period| self.noise4_with_period(frequency * p, frequency * w, period))
+    }
+
+    /// Octaves of the absolute value of noise added up, which has creases where the noise
+    /// crosses zero.
+    pub fn turbulence(&self, p: Vector3<f64>, octaves: Octaves) -> f64 {
+        self.sum_octaves(octaves, |frequency, period| self.noise_with_period(frequency * p, period).abs())
+    }
+
+    /// Turbulence of four dimensional noise.
+    pub fn turbulence4(&self, p: Vector3<f64>, w: f64, octaves: Octaves) -> f64 {
+        self.sum_octaves(octaves, |frequency, period| self.noise4_with_period(frequency * p, frequency * w, period).abs())
+    }
+
+    /// Largest value a sum of octaves with amplitudes of at most one can reach.
+    pub fn amplitude(octaves: Octaves) -> f64 {
+        (0..octaves.count).map(|i| octaves.gain.powi(i as i32)).sum()
+    }
+
+    /// Adds up `octave(frequency, period)` over the octaves. With a period, the frequency of
+    /// every octave is rounded to a whole number of lattice cells per period, so that every
+    /// octave repeats over the same distance also when the lacunarity isn't a whole number.
+    fn sum_octaves<F: Fn(f64, Option<i64>) -> f64>(&self, octaves: Octaves, octave: F) -> f64 {
         let mut accum: f64 = 0.0;
         let mut weight = 1.0;
+        let mut frequency = 1.0;
 
-        for _ in 0..depth {
-            accum += weight * self.noise(p);
-            weight *= 0.5;
-            p *= 2.0;
+        for _ in 0..octaves.count {
+            accum += weight * match self.period {
+                Some(period) => {
+                    let cells = (period as f64 * frequency).round().max(1.0);
+                    octave(cells / period as f64, Some(cells as i64))
+                }
+                None => octave(frequency, None),
+            };
+            weight *= octaves.gain;
+            frequency *= octaves.lacunarity;
         }
 
-        accum.abs()
+        accum
     }
 }
 
@@ -61,24 +162,46 @@ struct Permutation {
 }
 
 impl Permutation {
-    fn new() -> Permutation {
+    fn new<R: Rng>(rng: &mut R) -> Permutation {
         let mut data: [usize; 256] = array_init(|i| { i });
-        thread_rng().shuffle(&mut data);
+        rng.shuffle(&mut data);
         Permutation { data }
     }
 
+    /// Permuted indices of the lattice points below and above `x`, wrapped by the period.
     #[inline]
-    fn lookup(&self, index: f64) -> (usize, usize) {
-        let i = index.floor() as usize;
+    fn lookup(&self, x: f64, period: Option<i64>) -> (usize, usize) {
+        let i = x.floor() as i64;
+        let (i0, i1) = match period {
+            Some(period) => (i.rem_euclid(period), (i + 1).rem_euclid(period)),
+            None => (i, i + 1),
+        };
 
-        (self.data[i % 256], self.data[(i + 1) % 256])
+        (self.data[(i0 & 255) as usize], self.data[(i1 & 255) as usize])
     }
 }
 
-fn random_around_zero() -> f64 {
-    -1.0 + 2.0 * random::<f64>()
+/// Generator for the tables of noise with the given seed.
+fn seeded_rng(seed: u64) -> XorShiftRng {
+    // Spread the seed over all bits with splitmix64, xorshift must not start from zero.
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    let (a, b) = (next(), next());
+    XorShiftRng::from_seed([a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32 | 1])
 }
 
+fn random_around_zero<R: Rng>(rng: &mut R) -> f64 {
+    -1.0 + 2.0 * rng.next_f64()
+}
+
+/// The classic marble of the book, kept for existing scenes. `procedural::Marble` is the
+/// configurable version.
 pub struct NoiseTexture {
     scale: f64,
     perlin: Perlin,
@@ -88,20 +211,30 @@ impl NoiseTexture {
     pub fn new(scale: f64) -> NoiseTexture {
         NoiseTexture {
             scale,
-            perlin: Perlin::new(),
+            perlin: Perlin::new(0),
         }
     }
+
+    pub fn with_noise(mut self, perlin: Perlin) -> NoiseTexture {
+        self.perlin = perlin;
+        self
+    }
 }
 
 impl Texture for NoiseTexture {
     fn value(&self, _u: f64, _v: f64, p: &Point3<f64>) -> Color {
-//        let s = 0.5 * (1.0 + self.perlin.noise(self.scale * p.to_vec()));
-//        let s = self.perlin.turb(self.scale * p.to_vec());
         let s = 0.5 * (self.scale * p.z + 10.0 * self.perlin.turb(p.to_vec())).sin();
         s * Color::white()
     }
 }
 
+/// The marble pattern of the noise texture, rescaled to `[0, 1]` for use as a mask.
+impl ScalarTexture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3<f64>) -> f64 {
+        0.5 * (1.0 + (self.scale * p.z + 10.0 * self.perlin.turb(p.to_vec())).sin())
+    }
+}
+
 fn perlin_interp(c: &[[[Vector3<f64>; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
     let uu = hermite_cubic(u);
     let vv = hermite_cubic(v);
@@ -126,6 +259,12 @@ fn perlin_interp(c: &[[[Vector3<f64>; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64
     sum
 }
 
+/// Weight of the lower (`i == 0`) or upper lattice point at smoothed fraction `s`.
+#[inline]
+fn blend(i: usize, s: f64) -> f64 {
+    if i == 0 { 1.0 - s } else { s }
+}
+
 #[inline]
 fn hermite_cubic(x: f64) -> f64 {
     x * x * (3.0 - 2.0 * x)
@@ -135,3 +274,82 @@ fn hermite_cubic(x: f64) -> f64 {
 fn fraction(x: f64) -> f64 {
     x - x.floor()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points() -> Vec<Vector3<f64>> {
+        let mut rng = XorShiftRng::from_seed([4, 3, 2, 1]);
+        (0..1000).map(|_| vec3(rng.gen_range(-40.0, 40.0), rng.gen_range(-40.0, 40.0), rng.gen_range(-40.0, 40.0))).collect()
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_noise() {
+        let (a, b, other) = (Perlin::new(42), Perlin::new(42), Perlin::new(43));
+        let octaves = Octaves::default();
+        for p in points() {
+            assert_eq!(a.noise(p), b.noise(p));
+            assert_eq!(a.fbm4(p, 0.3, octaves), b.fbm4(p, 0.3, octaves));
+        }
+        assert!(points().iter().any(|&p| a.noise(p) != other.noise(p)));
+    }
+
+    #[test]
+    fn periodic_noise_repeats() {
+        let perlin = Perlin::new(7).with_period(5);
+        // Whole and fractional lacunarity, the latter as in the procedural scene.
+        for &octaves in &[Octaves::new(4, 2.0, 0.5), Octaves::new(5, 2.2, 0.6)] {
+            for p in points() {
+                for &offset in &[vec3(5.0, 0.0, 0.0), vec3(0.0, -10.0, 0.0), vec3(5.0, 5.0, 15.0)] {
+                    let q = p + offset;
+                    assert!((perlin.noise(p) - perlin.noise(q)).abs() < 1e-9, "noise differs at {:?} and {:?}", p, q);
+                    assert!((perlin.fbm(p, octaves) - perlin.fbm(q, octaves)).abs() < 1e-9, "fbm with {:?} differs at {:?} and {:?}", octaves, p, q);
+                    assert!((perlin.turbulence(p, octaves) - perlin.turbulence(q, octaves)).abs() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn periodic_four_dimensional_noise_tiles_in_space_only() {
+        let perlin = Perlin::new(7).with_period(5);
+        let octaves = Octaves::new(4, 2.0, 0.5);
+        for p in points() {
+            let q = p + vec3(5.0, -5.0, 10.0);
+            assert!((perlin.fbm4(p, 0.7, octaves) - perlin.fbm4(q, 0.7, octaves)).abs() < 1e-9, "fbm4 differs at {:?} and {:?}", p, q);
+        }
+        assert!(points().iter().any(|&p| (perlin.fbm4(p, 0.7, octaves) - perlin.fbm4(p, 5.7, octaves)).abs() > 1e-3), "fbm4 loops in time");
+    }
+
+    #[test]
+    fn noise_is_continuous_across_zero() {
+        let perlin = Perlin::new(3);
+        let step = 1e-7;
+        for p in points() {
+            for axis in 0..3 {
+                let mut below = p;
+                below[axis] = -step;
+                let mut above = p;
+                above[axis] = step;
+                assert!((perlin.noise(below) - perlin.noise(above)).abs() < 1e-5, "noise jumps across zero at {:?}", p);
+                assert!((perlin.fbm4(below, -step, Octaves::default()) - perlin.fbm4(above, step, Octaves::default())).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn four_dimensional_noise_changes_continuously_with_time() {
+        let perlin = Perlin::new(11);
+        let octaves = Octaves::default();
+        for p in points().into_iter().take(100) {
+            let mut w = -2.0;
+            while w < 2.0 {
+                let step = 1e-6;
+                let change = (perlin.fbm4(p, w + step, octaves) - perlin.fbm4(p, w, octaves)).abs();
+                assert!(change < 1e-3, "fbm4 jumps by {} at w = {} and {:?}", change, w, p);
+                w += 0.013;
+            }
+        }
+    }
+}
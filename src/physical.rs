@@ -0,0 +1,93 @@
+/// Size of the image sensor. Dimensions are in millimeters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SensorFormat {
+    FullFrame,
+    ApsC,
+    MicroFourThirds,
+    Custom { width: f64, height: f64 },
+}
+
+impl SensorFormat {
+    pub fn parse(name: &str) -> Option<SensorFormat> {
+        match name {
+            "full-frame" => Some(SensorFormat::FullFrame),
+            "aps-c" => Some(SensorFormat::ApsC),
+            "micro-four-thirds" => Some(SensorFormat::MicroFourThirds),
+            _ => {
+                // Custom sizes are given as WIDTHxHEIGHT in millimeters.
+                let mut parts = name.split('x').map(|p| p.parse::<f64>());
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some(Ok(width)), Some(Ok(height)), None) => Some(SensorFormat::Custom { width, height }),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    pub fn width(&self) -> f64 {
+        match *self {
+            SensorFormat::FullFrame => 36.0,
+            SensorFormat::ApsC => 23.6,
+            SensorFormat::MicroFourThirds => 17.3,
+            SensorFormat::Custom { width, .. } => width,
+        }
+    }
+
+    pub fn height(&self) -> f64 {
+        match *self {
+            SensorFormat::FullFrame => 24.0,
+            SensorFormat::ApsC => 15.7,
+            SensorFormat::MicroFourThirds => 13.0,
+            SensorFormat::Custom { height, .. } => height,
+        }
+    }
+}
+
+/// Number of scene units in a millimeter. Scenes are modelled in meters.
+const SCENE_UNITS_PER_MM: f64 = 0.001;
+
+/// Camera described the way a photographer would. The abstract parameters the camera and
+/// film need are derived from these.
+#[derive(Debug, Clone, Copy)]
+pub struct PhysicalCameraSettings {
+    /// Focal length in millimeters.
+    pub focal_length: f64,
+    pub sensor: SensorFormat,
+    pub f_number: f64,
+    /// Time the shutter stays open, in seconds.
+    pub shutter_duration: f64,
+    pub iso: f64,
+}
+
+impl Default for PhysicalCameraSettings {
+    fn default() -> PhysicalCameraSettings {
+        PhysicalCameraSettings {
+            focal_length: 50.0,
+            sensor: SensorFormat::FullFrame,
+            f_number: 16.0,
+            shutter_duration: 0.01,
+            iso: 100.0,
+        }
+    }
+}
+
+impl PhysicalCameraSettings {
+    /// Vertical field of view in degrees for an image with the given aspect ratio. The image
+    /// is cropped from the sensor so that it spans either its whole width or whole height.
+    pub fn vfov(&self, aspect: f64) -> f64 {
+        let film_height = (self.sensor.width() / aspect).min(self.sensor.height());
+        2.0 * (film_height / (2.0 * self.focal_length)).atan().to_degrees()
+    }
+
+    /// Diameter of the entrance pupil in scene units.
+    pub fn aperture(&self) -> f64 {
+        self.focal_length / self.f_number * SCENE_UNITS_PER_MM
+    }
+
+    /// Factor to scale the radiance reaching the film with. Calibrated with the sunny 16 rule:
+    /// ISO 100 at 1/100 s and f/16 leaves a white sky at 1.0.
+    pub fn exposure(&self) -> f64 {
+        const SUNNY_16: f64 = 16.0 * 16.0 / (0.01 * 100.0);
+        self.shutter_duration * self.iso / (self.f_number * self.f_number) * SUNNY_16
+    }
+}
@@ -0,0 +1,247 @@
+use cgmath::prelude::*;
+use color::Color;
+use hitable::HitRecord;
+use material::{Material, next_material_id};
+use microfacet::{Ggx, schlick_fresnel};
+use rand::random;
+use ray::Ray;
+use sampling::{Onb, sample_cosine_hemisphere};
+use texture::{ScalarTexture, Texture};
+
+/// Artist friendly uber material after the Disney principled BSDF. It mixes a diffuse base
+/// with sheen, a metallic or dielectric specular layer, a clearcoat and rough transmission,
+/// with every parameter driven by a texture.
+///
+/// Each scattering event picks one of the lobes with a probability based on how much it
+/// reflects and divides its weight by that probability, so that on average the lobes add up.
+/// The clearcoat uses GGX instead of the GTR1 distribution of the original.
+pub struct Principled {
+    base_color: Box<Texture>,
+    metallic: Box<ScalarTexture>,
+    roughness: Box<ScalarTexture>,
+    specular: Box<ScalarTexture>,
+    specular_tint: Box<ScalarTexture>,
+    sheen: Box<ScalarTexture>,
+    sheen_tint: Box<ScalarTexture>,
+    clearcoat: Box<ScalarTexture>,
+    clearcoat_gloss: Box<ScalarTexture>,
+    transmission: Box<ScalarTexture>,
+    ior: Box<ScalarTexture>,
+    anisotropy: Box<ScalarTexture>,
+    id: usize,
+}
+
+/// Parameters evaluated at a hit point.
+struct Parameters {
+    base_color: Color,
+    metallic: f64,
+    roughness: f64,
+    specular: f64,
+    specular_tint: f64,
+    sheen: f64,
+    sheen_tint: f64,
+    clearcoat: f64,
+    clearcoat_gloss: f64,
+    transmission: f64,
+    ior: f64,
+    anisotropy: f64,
+}
+
+impl Principled {
+    /// Dielectric with the given base color, roughness 0.5 and specular 0.5, which
+    /// corresponds to an index of refraction of 1.5.
+    pub fn new(base_color: Box<Texture>) -> Principled {
+        Principled {
+            base_color,
+            metallic: Box::new(0.0),
+            roughness: Box::new(0.5),
+            specular: Box::new(0.5),
+            specular_tint: Box::new(0.0),
+            sheen: Box::new(0.0),
+            sheen_tint: Box::new(0.5),
+            clearcoat: Box::new(0.0),
+            clearcoat_gloss: Box::new(1.0),
+            transmission: Box::new(0.0),
+            ior: Box::new(1.5),
+            anisotropy: Box::new(0.0),
+            id: next_material_id(),
+        }
+    }
+
+    pub fn with_metallic(mut self, metallic: Box<ScalarTexture>) -> Principled {
+        self.metallic = metallic;
+        self
+    }
+
+    pub fn with_roughness(mut self, roughness: Box<ScalarTexture>) -> Principled {
+        self.roughness = roughness;
+        self
+    }
+
+    /// Specular reflectance of dielectrics, where 0.5 is 4% at normal incidence.
+    pub fn with_specular(mut self, specular: Box<ScalarTexture>) -> Principled {
+        self.specular = specular;
+        self
+    }
+
+    /// How much the dielectric specular takes on the base color.
+    pub fn with_specular_tint(mut self, specular_tint: Box<ScalarTexture>) -> Principled {
+        self.specular_tint = specular_tint;
+        self
+    }
+
+    /// Extra grazing retroreflection for cloth, tinted towards the base color by `sheen_tint`.
+    pub fn with_sheen(mut self, sheen: Box<ScalarTexture>, sheen_tint: Box<ScalarTexture>) -> Principled {
+        self.sheen = sheen;
+        self.sheen_tint = sheen_tint;
+        self
+    }
+
+    /// Second, colorless specular layer on top, whose sharpness is given by `gloss`.
+    pub fn with_clearcoat(mut self, clearcoat: Box<ScalarTexture>, gloss: Box<ScalarTexture>) -> Principled {
+        self.clearcoat = clearcoat;
+        self.clearcoat_gloss = gloss;
+        self
+    }
+
+    /// Fraction of the dielectric base that transmits light instead of scattering it
+    /// diffusely, refracting with the given index of refraction.
+    pub fn with_transmission(mut self, transmission: Box<ScalarTexture>, ior: Box<ScalarTexture>) -> Principled {
+        self.transmission = transmission;
+        self.ior = ior;
+        self
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: Box<ScalarTexture>) -> Principled {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    fn parameters(&self, rec: &HitRecord) -> Parameters {
+        let (u, v, p) = (rec.u, rec.v, &rec.p);
+        Parameters {
+            base_color: self.base_color.value(u, v, p),
+            metallic: self.metallic.value(u, v, p),
+            roughness: self.roughness.value(u, v, p),
+            specular: self.specular.value(u, v, p),
+            specular_tint: self.specular_tint.value(u, v, p),
+            sheen: self.sheen.value(u, v, p),
+            sheen_tint: self.sheen_tint.value(u, v, p),
+            clearcoat: self.clearcoat.value(u, v, p),
+            clearcoat_gloss: self.clearcoat_gloss.value(u, v, p),
+            transmission: self.transmission.value(u, v, p),
+            ior: self.ior.value(u, v, p),
+            anisotropy: self.anisotropy.value(u, v, p),
+        }
+    }
+}
+
+impl Parameters {
+    /// Base color normalized to unit luminance, which tints specular and sheen.
+    fn tint(&self) -> Color {
+        let luminance = self.base_color.luminance();
+        if luminance > 0.0 { self.base_color / luminance } else { Color::white() }
+    }
+
+    /// Specular reflectance at normal incidence.
+    fn specular_f0(&self) -> Color {
+        let tinted = mix(Color::white(), self.tint(), self.specular_tint);
+        mix(0.08 * self.specular * tinted, self.base_color, self.metallic)
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        let params = self.parameters(rec);
+        let entering = rec.front_face;
+        let frame = Onb::from_normal(rec.facing_normal());
+        let wo = frame.to_local(-r_in.direction.normalize());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let specular = Ggx::anisotropic(params.roughness, params.anisotropy);
+
+        // From inside only the transmission lobe can have let the ray in.
+        if !entering {
+            let (wi, weight) = specular.sample_dielectric(wo, 1.0 / params.ior)?;
+            let tint = if wi.z < 0.0 { sqrt_color(params.base_color) } else { Color::white() };
+            return Some((Ray::new(rec.p, frame.to_world(wi), r_in.time), weight * tint));
+        }
+
+        let dielectric = (1.0 - params.metallic) * (1.0 - params.transmission);
+        let transmissive = (1.0 - params.metallic) * params.transmission;
+        let f0 = params.specular_f0();
+
+        let weights = [
+            dielectric * (params.base_color.luminance() + params.sheen),
+            f0.luminance().max(0.02) * (1.0 - transmissive),
+            transmissive,
+            0.25 * params.clearcoat,
+        ];
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let mut choice = random::<f64>() * total;
+        let mut lobe = 0;
+        while lobe < weights.len() - 1 && choice >= weights[lobe] {
+            choice -= weights[lobe];
+            lobe += 1;
+        }
+        let probability = weights[lobe] / total;
+
+        let (wi, attenuation) = match lobe {
+            0 => {
+                let wi = sample_cosine_hemisphere();
+                let h = (wi + wo).normalize();
+                let cos_d = wi.dot(h);
+                let fd90 = 0.5 + 2.0 * params.roughness * cos_d * cos_d;
+                let retro = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z)) * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z));
+                let sheen = params.sheen * schlick_weight(cos_d) * mix(Color::white(), params.tint(), params.sheen_tint);
+                (wi, dielectric * (retro * params.base_color + sheen))
+            }
+            1 => {
+                let (wi, h, weight) = specular.sample_reflection(wo)?;
+                (wi, weight * (1.0 - transmissive) * schlick_fresnel(f0, wo.dot(h)))
+            }
+            2 => {
+                let (wi, weight) = specular.sample_dielectric(wo, params.ior)?;
+                let tint = if wi.z < 0.0 { sqrt_color(params.base_color) } else { Color::white() };
+                (wi, transmissive * weight * tint)
+            }
+            _ => {
+                let gloss = 0.1 + (0.001 - 0.1) * params.clearcoat_gloss;
+                let coat = Ggx { alpha_x: gloss, alpha_y: gloss };
+                let (wi, h, weight) = coat.sample_reflection(wo)?;
+                let fresnel = schlick_fresnel(Color::new(0.04, 0.04, 0.04), wo.dot(h));
+                (wi, 0.25 * params.clearcoat * weight * fresnel)
+            }
+        };
+
+        Some((Ray::new(rec.p, frame.to_world(wi), r_in.time), attenuation / probability))
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base_color.value(rec.u, rec.v, &rec.p)
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+}
+
+fn mix(a: Color, b: Color, amount: f64) -> Color {
+    (1.0 - amount) * a + amount * b
+}
+
+fn schlick_weight(cosine: f64) -> f64 {
+    (1.0 - cosine).clamp(0.0, 1.0).powi(5)
+}
+
+/// Tint applied on each crossing of a transmissive surface, so that light passing through
+/// an object in and out again takes on the base color.
+fn sqrt_color(color: Color) -> Color {
+    Color::new(color.r.sqrt(), color.g.sqrt(), color.b.sqrt())
+}
@@ -0,0 +1,594 @@
+use cgmath::{Point3, Vector3, vec3};
+use cgmath::prelude::*;
+use color::Color;
+use perlin::{Octaves, Perlin};
+use std::str::FromStr;
+use texture::{ScalarTexture, Texture};
+
+/// Seeds of the noise of textures that aren't given other noise. Every kind of texture has
+/// its own, so that combinations like a warped wood don't line up with themselves.
+const FBM_SEED: u64 = 1;
+const TURBULENCE_SEED: u64 = 2;
+const MARBLE_SEED: u64 = 3;
+const WOOD_SEED: u64 = 4;
+const WORLEY_SEED: u64 = 5;
+const RIDGED_SEED: u64 = 6;
+const WARP_SEED: u64 = 7;
+
+/// Colors interpolated linearly between stops at positions in `[0, 1]`.
+#[derive(Clone)]
+pub struct ColorRamp {
+    stops: Vec<(f64, Color)>,
+}
+
+impl ColorRamp {
+    pub fn new(mut stops: Vec<(f64, Color)>) -> ColorRamp {
+        assert!(!stops.is_empty(), "a color ramp needs stops");
+        assert!(stops.iter().all(|stop| !stop.0.is_nan()), "the positions of color ramp stops must be numbers");
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).expect("NaN stop position"));
+        ColorRamp { stops }
+    }
+
+    pub fn value(&self, t: f64) -> Color {
+        let first = self.stops[0];
+        if t <= first.0 {
+            return first.1;
+        }
+        for pair in self.stops.windows(2) {
+            let ((t0, c0), (t1, c1)) = (pair[0], pair[1]);
+            if t <= t1 {
+                let f = if t1 > t0 { (t - t0) / (t1 - t0) } else { 1.0 };
+                return (1.0 - f) * c0 + f * c1;
+            }
+        }
+        self.stops[self.stops.len() - 1].1
+    }
+}
+
+/// Colors a scalar texture with a ramp. The procedural textures here are all scalar and in
+/// `[0, 1]`, so they serve as masks, roughness or heights directly and get their colors this
+/// way. Their `scale` is the frequency of the pattern in world space.
+pub struct RampTexture {
+    scalar: Box<ScalarTexture>,
+    ramp: ColorRamp,
+}
+
+impl RampTexture {
+    pub fn new(scalar: Box<ScalarTexture>, ramp: ColorRamp) -> RampTexture {
+        RampTexture { scalar, ramp }
+    }
+}
+
+impl Texture for RampTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3<f64>) -> Color {
+        self.ramp.value(self.scalar.value(u, v, p))
+    }
+}
+
+/// Fractional Brownian motion, the soft cloudy noise most natural patterns start from.
+pub struct Fbm {
+    perlin: Perlin,
+    scale: f64,
+    octaves: Octaves,
+    time: Option<f64>,
+}
+
+impl Fbm {
+    pub fn new(scale: f64) -> Fbm {
+        Fbm { perlin: Perlin::new(FBM_SEED), scale, octaves: Octaves::default(), time: None }
+    }
+
+    pub fn with_octaves(mut self, octaves: Octaves) -> Fbm {
+        self.octaves = octaves;
+        self
+    }
+
+    /// Uses other noise than the default, with another seed or a period.
+    pub fn with_noise(mut self, perlin: Perlin) -> Fbm {
+        self.perlin = perlin;
+        self
+    }
+
+    /// Evaluates four dimensional noise at `time`, so the pattern changes smoothly when it is
+    /// rebuilt for every frame. Scale the time to set the speed.
+    pub fn with_time(mut self, time: f64) -> Fbm {
+        self.time = Some(time);
+        self
+    }
+}
+
+impl ScalarTexture for Fbm {
+    fn value(&self, _u: f64, _v: f64, p: &Point3<f64>) -> f64 {
+        let p = self.scale * p.to_vec();
+        let fbm = match self.time {
+            Some(time) => self.perlin.fbm4(p, time, self.octaves),
+            None => self.perlin.fbm(p, self.octaves),
+        };
+        let fbm = fbm / Perlin::amplitude(self.octaves);
+        (0.5 * (1.0 + fbm)).clamp(0.0, 1.0)
+    }
+}
+
+/// Sum of the absolute values of octaves of noise, with sharp creases, like flames or
+/// billowing smoke.
+pub struct Turbulence {
+    perlin: Perlin,
+    scale: f64,
+    octaves: Octaves,
+    time: Option<f64>,
+}
+
+impl Turbulence {
+    pub fn new(scale: f64) -> Turbulence {
+        Turbulence { perlin: Perlin::new(TURBULENCE_SEED), scale, octaves: Octaves::default(), time: None }
+    }
+
+    pub fn with_octaves(mut self, octaves: Octaves) -> Turbulence {
+        self.octaves = octaves;
+        self
+    }
+
+    pub fn with_noise(mut self, perlin: Perlin) -> Turbulence {
+        self.perlin = perlin;
+        self
+    }
+
+    /// Animates the turbulence like `Fbm::with_time`.
+    pub fn with_time(mut self, time: f64) -> Turbulence {
+        self.time = Some(time);
+        self
+    }
+}
+
+impl ScalarTexture for Turbulence {
+    fn value(&self, _u: f64, _v: f64, p: &Point3<f64>) -> f64 {
+        let p = self.scale * p.to_vec();
+        let turbulence = match self.time {
+            Some(time) => self.perlin.turbulence4(p, time, self.octaves),
+            None => self.perlin.turbulence(p, self.octaves),
+        };
+        (turbulence / Perlin::amplitude(self.octaves)).clamp(0.0, 1.0)
+    }
+}
+
+/// Marble veins: stripes along `z` at the given frequency, bent by `distortion` times
+/// turbulence.
+pub struct Marble {
+    perlin: Perlin,
+    scale: f64,
+    distortion: f64,
+    octaves: Octaves,
+}
+
+impl Marble {
+    pub fn new(scale: f64, distortion: f64) -> Marble {
+        Marble { perlin: Perlin::new(MARBLE_SEED), scale, distortion, octaves: Octaves::default() }
+    }
+
+    pub fn with_octaves(mut self, octaves: Octaves) -> Marble {
+        self.octaves = octaves;
+        self
+    }
+
+    pub fn with_noise(mut self, perlin: Perlin) -> Marble {
+        self.perlin = perlin;
+        self
+    }
+}
+
+impl ScalarTexture for Marble {
+    fn value(&self, _u: f64, _v: f64, p: &Point3<f64>) -> f64 {
+        let turbulence = self.perlin.turbulence(p.to_vec(), self.octaves);
+        0.5 * (1.0 + (self.scale * p.z + self.distortion * turbulence).sin())
+    }
+}
+
+/// Growth rings of wood around the `y` axis, `scale` rings per unit, wobbled by
+/// `distortion` rings of noise. The value rises from zero to one across every ring.
+pub struct Wood {
+    perlin: Perlin,
+    scale: f64,
+    distortion: f64,
+}
+
+impl Wood {
+    pub fn new(scale: f64, distortion: f64) -> Wood {
+        Wood { perlin: Perlin::new(WOOD_SEED), scale, distortion }
+    }
+
+    pub fn with_noise(mut self, perlin: Perlin) -> Wood {
+        self.perlin = perlin;
+        self
+    }
+}
+
+impl ScalarTexture for Wood {
+    fn value(&self, _u: f64, _v: f64, p: &Point3<f64>) -> f64 {
+        // The noise is stretched along the grain.
+        let wobble = self.perlin.fbm(vec3(2.0 * p.x, 0.25 * p.y, 2.0 * p.z), Octaves::new(3, 2.0, 0.5));
+        let rings = self.scale * (p.x * p.x + p.z * p.z).sqrt() + self.distortion * wobble;
+        rings - rings.floor()
+    }
+}
+
+/// Which distances to the nearest feature points a Worley texture shows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WorleyFeature {
+    /// Distance to the nearest point: round cells, dark at their centers.
+    F1,
+    /// Distance to the second nearest point.
+    F2,
+    /// Difference of the two: thin dark lines along the cell borders, like cracks or scales.
+    F2MinusF1,
+}
+
+/// Cellular noise after Steven Worley, from one randomly placed feature point per unit cell.
+/// The seed decides where the points are.
+pub struct Worley {
+    scale: f64,
+    feature: WorleyFeature,
+    seed: u64,
+}
+
+impl Worley {
+    pub fn new(scale: f64, feature: WorleyFeature) -> Worley {
+        Worley { scale, feature, seed: WORLEY_SEED }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Worley {
+        self.seed = seed;
+        self
+    }
+
+    /// Distances to the nearest and the second nearest feature point.
+    fn distances(&self, p: Vector3<f64>) -> (f64, f64) {
+        let cell = vec3(p.x.floor(), p.y.floor(), p.z.floor());
+        let (mut f1, mut f2) = (f64::INFINITY, f64::INFINITY);
+        for dx in -1..2 {
+            for dy in -1..2 {
+                for dz in -1..2 {
+                    let neighbor = cell + vec3(dx as f64, dy as f64, dz as f64);
+                    let hash = hash_cell(neighbor.x as i64, neighbor.y as i64, neighbor.z as i64, self.seed);
+                    let offset = vec3(unit(hash), unit(hash >> 21), unit(hash >> 42));
+                    let distance = (neighbor + offset - p).magnitude();
+                    if distance < f1 {
+                        f2 = f1;
+                        f1 = distance;
+                    } else if distance < f2 {
+                        f2 = distance;
+                    }
+                }
+            }
+        }
+        (f1, f2)
+    }
+}
+
+impl ScalarTexture for Worley {
+    fn value(&self, _u: f64, _v: f64, p: &Point3<f64>) -> f64 {
+        let (f1, f2) = self.distances(self.scale * p.to_vec());
+        let distance = match self.feature {
+            WorleyFeature::F1 => f1,
+            WorleyFeature::F2 => f2,
+            WorleyFeature::F2MinusF1 => f2 - f1,
+        };
+        distance.clamp(0.0, 1.0)
+    }
+}
+
+/// Mixes the bits of a cell's coordinates and a seed into a pseudo-random number.
+fn hash_cell(x: i64, y: i64, z: i64, seed: u64) -> u64 {
+    let mut h = seed ^ (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    h ^= (y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+    h ^= (z as u64).wrapping_mul(0x1656_67b1_9e37_79f9);
+    // Finalizer of splitmix64.
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
+}
+
+/// Maps the low 21 bits of a hash to `[0, 1)`.
+fn unit(hash: u64) -> f64 {
+    (hash & 0x1f_ffff) as f64 / (1u64 << 21) as f64
+}
+
+/// Ridged multifractal after Musgrave: inverted creases of noise that get rougher on the
+/// ridges, like mountain ranges or veins. `offset` around one raises the ridges.
+pub struct Ridged {
+    perlin: Perlin,
+    scale: f64,
+    offset: f64,
+    octaves: Octaves,
+}
+
+impl Ridged {
+    pub fn new(scale: f64) -> Ridged {
+        Ridged { perlin: Perlin::new(RIDGED_SEED), scale, offset: 1.0, octaves: Octaves::default() }
+    }
+
+    pub fn with_offset(mut self, offset: f64) -> Ridged {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_octaves(mut self, octaves: Octaves) -> Ridged {
+        self.octaves = octaves;
+        self
+    }
+
+    pub fn with_noise(mut self, perlin: Perlin) -> Ridged {
+        self.perlin = perlin;
+        self
+    }
+}
+
+impl ScalarTexture for Ridged {
+    fn value(&self, _u: f64, _v: f64, p: &Point3<f64>) -> f64 {
+        let mut p = self.scale * p.to_vec();
+        let (mut sum, mut amplitude, mut weight) = (0.0, 1.0, 1.0);
+        for _ in 0..self.octaves.count {
+            let signal = (self.offset - self.perlin.noise(p).abs()).powi(2) * weight;
+            sum += amplitude * signal;
+            // Octaves only add detail where the previous ones made ridges.
+            weight = (2.0 * signal).clamp(0.0, 1.0);
+            amplitude *= self.octaves.gain;
+            p *= self.octaves.lacunarity;
+        }
+        (sum / (self.offset * self.offset * Perlin::amplitude(self.octaves))).clamp(0.0, 1.0)
+    }
+}
+
+/// Looks up another scalar texture at a point pushed around by noise, which swirls and
+/// stretches its pattern, like folded rock or flowing paint.
+pub struct DomainWarp {
+    texture: Box<ScalarTexture>,
+    perlin: Perlin,
+    scale: f64,
+    amount: f64,
+}
+
+impl DomainWarp {
+    pub fn new(texture: Box<ScalarTexture>, scale: f64, amount: f64) -> DomainWarp {
+        DomainWarp { texture, perlin: Perlin::new(WARP_SEED), scale, amount }
+    }
+
+    pub fn with_noise(mut self, perlin: Perlin) -> DomainWarp {
+        self.perlin = perlin;
+        self
+    }
+}
+
+impl ScalarTexture for DomainWarp {
+    fn value(&self, u: f64, v: f64, p: &Point3<f64>) -> f64 {
+        let q = self.scale * p.to_vec();
+        let octaves = Octaves::new(4, 2.0, 0.5);
+        // Differently offset lookups give independent noise for each axis.
+        let warp = vec3(
+            self.perlin.fbm(q, octaves),
+            self.perlin.fbm(q + vec3(5.2, 1.3, 2.8), octaves),
+            self.perlin.fbm(q + vec3(1.7, 9.2, 4.1), octaves),
+        );
+        self.texture.value(u, v, &(p + self.amount * warp))
+    }
+}
+
+/// Builds a scalar texture from a description like `marble:scale=4,distortion=8,octaves=5`,
+/// as given on the command line. The name is one of `fbm`, `turbulence`, `marble`, `wood`,
+/// `worley`, `ridged`, followed by the parameters of its constructor and builders:
+///
+/// - `scale`, and `distortion` for marble and wood,
+/// - `octaves`, `lacunarity` and `gain` of the sums of noise,
+/// - `seed` and `period` of the noise,
+/// - `time` for fbm and turbulence, `offset` for ridged,
+/// - `feature` for worley, one of `f1`, `f2`, `f2-f1`,
+/// - `warp` and `warp-scale` to warp any of them with `DomainWarp`.
+pub fn parse_texture(spec: &str) -> Result<Box<ScalarTexture>, String> {
+    let mut parts = spec.splitn(2, ':');
+    let name = parts.next().unwrap_or("");
+    let mut params = TextureParams::parse(parts.next().unwrap_or(""))?;
+
+    let octaves = Octaves::default();
+    let octaves = Octaves::new(params.count("octaves", octaves.count)?, params.number("lacunarity", octaves.lacunarity)?, params.number("gain", octaves.gain)?);
+    let seed: Option<u64> = params.optional_count("seed")?;
+    let period: Option<u32> = params.optional_count("period")?;
+    if period == Some(0) {
+        return Err("the period of noise must be positive".to_string());
+    }
+    let noise = |seed: u64| match period {
+        Some(period) => Perlin::new(seed).with_period(period),
+        None => Perlin::new(seed),
+    };
+
+    let texture: Box<ScalarTexture> = match name {
+        "fbm" => {
+            let fbm = Fbm::new(params.number("scale", 1.0)?).with_octaves(octaves).with_noise(noise(seed.unwrap_or(FBM_SEED)));
+            match params.optional_number("time")? {
+                Some(time) => Box::new(fbm.with_time(time)),
+                None => Box::new(fbm),
+            }
+        }
+        "turbulence" => {
+            let turbulence = Turbulence::new(params.number("scale", 1.0)?).with_octaves(octaves).with_noise(noise(seed.unwrap_or(TURBULENCE_SEED)));
+            match params.optional_number("time")? {
+                Some(time) => Box::new(turbulence.with_time(time)),
+                None => Box::new(turbulence),
+            }
+        }
+        "marble" => Box::new(Marble::new(params.number("scale", 4.0)?, params.number("distortion", 8.0)?).with_octaves(octaves).with_noise(noise(seed.unwrap_or(MARBLE_SEED)))),
+        "wood" => Box::new(Wood::new(params.number("scale", 6.0)?, params.number("distortion", 0.8)?).with_noise(noise(seed.unwrap_or(WOOD_SEED)))),
+        "worley" => {
+            let feature = match params.take("feature").as_deref() {
+                None | Some("f1") => WorleyFeature::F1,
+                Some("f2") => WorleyFeature::F2,
+                Some("f2-f1") => WorleyFeature::F2MinusF1,
+                Some(other) => return Err(format!("unknown worley feature '{}'", other)),
+            };
+            Box::new(Worley::new(params.number("scale", 1.0)?, feature).with_seed(seed.unwrap_or(WORLEY_SEED)))
+        }
+        "ridged" => Box::new(Ridged::new(params.number("scale", 1.0)?).with_offset(params.number("offset", 1.0)?).with_octaves(octaves).with_noise(noise(seed.unwrap_or(RIDGED_SEED)))),
+        _ => return Err(format!("unknown texture '{}'", name)),
+    };
+
+    let texture: Box<ScalarTexture> = match params.optional_number("warp")? {
+        Some(amount) => {
+            let warp = DomainWarp::new(texture, params.number("warp-scale", 1.0)?, amount);
+            // Noise of its own even when a seed is given, so the warp doesn't follow the pattern.
+            Box::new(warp.with_noise(noise(seed.map_or(WARP_SEED, |seed| seed.wrapping_add(1)))))
+        }
+        None => texture,
+    };
+
+    match params.unused() {
+        Some(key) => Err(format!("unknown parameter '{}' for texture '{}'", key, name)),
+        None => Ok(texture),
+    }
+}
+
+/// Parameters of a texture description, `KEY=VALUE` separated by commas, which are taken as
+/// they are used so that the ones left over can be reported.
+struct TextureParams {
+    params: Vec<(String, String)>,
+    used: Vec<String>,
+}
+
+impl TextureParams {
+    fn parse(params: &str) -> Result<TextureParams, String> {
+        let params = params.split(',')
+            .filter(|param| !param.is_empty())
+            .map(|param| {
+                let mut parts = param.splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some(key), Some(value)) => Ok((key.to_string(), value.to_string())),
+                    _ => Err(format!("expected KEY=VALUE instead of '{}'", param)),
+                }
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(TextureParams { params, used: Vec::new() })
+    }
+
+    /// Value of the parameter `key`, if it is given.
+    fn take(&mut self, key: &str) -> Option<String> {
+        self.used.push(key.to_string());
+        self.params.iter().rev().find(|param| param.0 == key).map(|param| param.1.clone())
+    }
+
+    /// Value of the numeric parameter `key`, if it is given.
+    fn optional_number(&mut self, key: &str) -> Result<Option<f64>, String> {
+        match self.take(key) {
+            Some(value) => match value.parse::<f64>() {
+                Ok(number) if number.is_finite() => Ok(Some(number)),
+                _ => Err(format!("invalid value '{}' for texture parameter '{}'", value, key)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Value of the numeric parameter `key`, or `default` if it isn't given.
+    fn number(&mut self, key: &str, default: f64) -> Result<f64, String> {
+        Ok(self.optional_number(key)?.unwrap_or(default))
+    }
+
+    /// Value of the parameter `key` that has to be a whole number of at least zero, if it is
+    /// given.
+    fn optional_count<T: FromStr>(&mut self, key: &str) -> Result<Option<T>, String> {
+        match self.take(key) {
+            Some(value) => value.parse().map(Some).map_err(|_| format!("invalid value '{}' for texture parameter '{}'", value, key)),
+            None => Ok(None),
+        }
+    }
+
+    /// Value of the parameter `key` that has to be a whole number of at least zero, or
+    /// `default` if it isn't given.
+    fn count<T: FromStr>(&mut self, key: &str, default: T) -> Result<T, String> {
+        Ok(self.optional_count(key)?.unwrap_or(default))
+    }
+
+    /// First parameter that was given but never asked for.
+    fn unused(&self) -> Option<&str> {
+        self.params.iter().map(|param| param.0.as_str()).find(|key| !self.used.iter().any(|used| used == key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_stay_within_the_unit_interval() {
+        let textures: Vec<(&str, Box<ScalarTexture>)> = vec![
+            ("fbm", Box::new(Fbm::new(1.3))),
+            ("fbm4", Box::new(Fbm::new(1.3).with_time(0.7))),
+            ("turbulence", Box::new(Turbulence::new(1.3).with_octaves(Octaves::new(5, 2.2, 0.6)))),
+            ("turbulence4", Box::new(Turbulence::new(1.3).with_time(0.7))),
+            ("marble", Box::new(Marble::new(4.0, 8.0))),
+            ("wood", Box::new(Wood::new(6.0, 0.8))),
+            ("worley f1", Box::new(Worley::new(2.0, WorleyFeature::F1))),
+            ("worley f2", Box::new(Worley::new(2.0, WorleyFeature::F2))),
+            ("worley f2-f1", Box::new(Worley::new(2.0, WorleyFeature::F2MinusF1))),
+            ("ridged", Box::new(Ridged::new(1.3).with_offset(0.9))),
+            ("warp", Box::new(DomainWarp::new(Box::new(Wood::new(8.0, 0.0)), 1.5, 0.4))),
+        ];
+        // A grid spanning many cells of every pattern, on both sides of the origin.
+        let steps: Vec<f64> = (0..20).map(|i| 0.61 * i as f64 - 5.9).collect();
+        for (name, texture) in textures {
+            for &x in &steps {
+                for &y in &steps {
+                    for &z in &steps {
+                        let value = texture.value(0.0, 0.0, &Point3::new(x, y, z));
+                        assert!((0.0..=1.0).contains(&value), "{} is {} at {}, {}, {}", name, value, x, y, z);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nearest_feature_is_never_farther_than_the_second() {
+        let worley = Worley::new(1.0, WorleyFeature::F1).with_seed(5);
+        // Points all over a few cells around the origin, including their borders.
+        let steps: Vec<f64> = (0..17).map(|i| 0.25 * i as f64 - 2.0).collect();
+        for &x in &steps {
+            for &y in &steps {
+                for &z in &steps {
+                    let (f1, f2) = worley.distances(vec3(x, y, z));
+                    assert!(f1 <= f2, "F1 {} is farther than F2 {} at {}, {}, {}", f1, f2, x, y, z);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ramps_interpolate_between_stops() {
+        let (black, red, white) = (Color::new(0.0, 0.0, 0.0), Color::new(1.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        // Given out of order, the stops are sorted.
+        let ramp = ColorRamp::new(vec![(1.0, white), (0.2, black), (0.6, red)]);
+        let close = |a: Color, b: Color| (a.r - b.r).abs() + (a.g - b.g).abs() + (a.b - b.b).abs() < 1e-12;
+        assert!(close(ramp.value(-1.0), black));
+        assert!(close(ramp.value(0.2), black));
+        assert!(close(ramp.value(0.4), Color::new(0.5, 0.0, 0.0)));
+        assert!(close(ramp.value(0.6), red));
+        assert!(close(ramp.value(0.7), Color::new(1.0, 0.25, 0.25)));
+        assert!(close(ramp.value(1.0), white));
+        assert!(close(ramp.value(2.0), white));
+    }
+
+    #[test]
+    #[should_panic(expected = "must be numbers")]
+    fn ramps_reject_nan_stops() {
+        ColorRamp::new(vec![(0.0, Color::white()), (f64::NAN, Color::white())]);
+    }
+
+    #[test]
+    fn parses_texture_descriptions() {
+        assert!(parse_texture("marble:scale=4,distortion=8,octaves=5").is_ok());
+        assert!(parse_texture("worley:feature=f2-f1,seed=3,warp=0.2").is_ok());
+        assert!(parse_texture("fbm:period=16,time=0.5").is_ok());
+        assert!(parse_texture("ridged").is_ok());
+        assert!(parse_texture("plaid").is_err());
+        assert!(parse_texture("marble:scale").is_err());
+        assert!(parse_texture("marble:scale=big").is_err());
+        assert!(parse_texture("wood:octaves=-1").is_err());
+        assert!(parse_texture("wood:warp-scale=2").is_err());
+        assert!(parse_texture("fbm:period=0").is_err());
+    }
+}
@@ -0,0 +1,113 @@
+use aabb::AABB;
+use cgmath::{Point3, Vector3, vec3};
+use cgmath::prelude::*;
+use hitable::{Hitable, HitRecord, next_object_id};
+use material::Material;
+use ray::Ray;
+use std::sync::Arc;
+
+/// Parallelogram spanned by the edges `u` and `v` from the corner `origin`, with texture
+/// coordinates running from zero to one along the edges. The front faces `cross(u, v)`.
+pub struct Quad {
+    origin: Point3<f64>,
+    u: Vector3<f64>,
+    v: Vector3<f64>,
+    normal: Vector3<f64>,
+    material: Arc<Material>,
+    object_id: usize,
+    material_id: usize,
+}
+
+impl Quad {
+    pub fn new(origin: Point3<f64>, u: Vector3<f64>, v: Vector3<f64>, material: Arc<Material>) -> Quad {
+        Quad {
+            origin,
+            u,
+            v,
+            normal: u.cross(v).normalize(),
+            object_id: next_object_id(),
+            material_id: material.id(),
+            material,
+        }
+    }
+}
+
+impl Hitable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < 1e-12 {
+            return None;
+        }
+        let t = self.normal.dot(self.origin - ray.origin) / denominator;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        // Coordinates of the hit along the edges, from the dual basis of the plane.
+        let p = ray.point_at(t);
+        let w = self.u.cross(self.v);
+        let w = w / w.magnitude2();
+        let d = p - self.origin;
+        let a = w.dot(d.cross(self.v));
+        let b = w.dot(self.u.cross(d));
+        if !(0.0..=1.0).contains(&a) || !(0.0..=1.0).contains(&b) {
+            return None;
+        }
+
+        let rec = HitRecord::new(ray, t, self.normal, (a, b), &self.material, self.object_id, self.material_id)
+            .with_derivatives(self.u, self.v);
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        let corners = [self.origin, self.origin + self.u, self.origin + self.v, self.origin + self.u + self.v];
+        let mut min = corners[0];
+        let mut max = corners[0];
+        for c in &corners[1..] {
+            min = Point3::new(min.x.min(c.x), min.y.min(c.y), min.z.min(c.z));
+            max = Point3::new(max.x.max(c.x), max.y.max(c.y), max.z.max(c.z));
+        }
+        // Keep axis aligned quads from giving flat boxes, which rays would miss.
+        let padding = vec3(1e-4, 1e-4, 1e-4);
+        Some(AABB::new(min - padding, max + padding))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec3;
+    use color::Color;
+    use material::Lambertian;
+
+    /// Two by one quad in the `xy` plane, starting at `(1, 1, 0)` and facing `+z`.
+    fn quad() -> Quad {
+        let material: Arc<Material> = Arc::new(Lambertian::new(Box::new(Color::white())));
+        Quad::new(Point3::new(1.0, 1.0, 0.0), vec3(2.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), material)
+    }
+
+    fn down_at(x: f64, y: f64) -> Ray {
+        Ray::new(Point3::new(x, y, 2.0), vec3(0.0, 0.0, -1.0), 0.0)
+    }
+
+    #[test]
+    fn hits_with_texture_coordinates_along_the_edges() {
+        let rec = quad().hit(&down_at(1.5, 1.25), 0.001, f64::MAX).expect("ray through the quad missed");
+        assert!((rec.t - 2.0).abs() < 1e-12);
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.25).abs() < 1e-12);
+        assert!(rec.front_face);
+        assert!((rec.normal - vec3(0.0, 0.0, 1.0)).magnitude() < 1e-12);
+        assert_eq!(rec.dpdu, vec3(2.0, 0.0, 0.0));
+        assert_eq!(rec.dpdv, vec3(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn misses_outside_the_edges() {
+        let quad = quad();
+        for &(x, y) in &[(0.9, 1.5), (3.1, 1.5), (2.0, 0.9), (2.0, 2.1)] {
+            assert!(quad.hit(&down_at(x, y), 0.001, f64::MAX).is_none(), "ray at {}, {} hit", x, y);
+        }
+        let parallel = Ray::new(Point3::new(0.0, 1.5, 0.0), vec3(1.0, 0.0, 0.0), 0.0);
+        assert!(quad.hit(&parallel, 0.001, f64::MAX).is_none());
+    }
+}
@@ -6,6 +6,8 @@ pub struct Ray {
     pub origin: Point3<f64>,
     pub direction: Vector3<f64>,
     pub time: f64,
+    /// Wavelength in nanometers, for paths through dispersive materials.
+    pub wavelength: Option<f64>,
 }
 
 impl Ray {
@@ -14,6 +16,7 @@ impl Ray {
             origin,
             direction,
             time,
+            wavelength: None,
         }
     }
 
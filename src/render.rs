@@ -0,0 +1,474 @@
+use camera::{Camera, film_coordinates};
+use cgmath::Point3;
+use cgmath::prelude::*;
+use color::Color;
+use film::{Channel, Film};
+use hitable::{Hitable, HitRecord};
+use medium::Medium;
+use rand::random;
+use spectrum;
+use spectrum::{SampledWavelengths, Spectrum};
+use ray::Ray;
+use std::io::prelude::*;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const MAX_DEPTH: u32 = 50;
+
+/// Most scattering events followed inside a medium between two surfaces. Translucent
+/// materials with a short mean free path need hundreds of them.
+const MAX_VOLUME_BOUNCES: u32 = 1024;
+
+/// Radiance carried by a single camera ray, split by the number of bounces, together with
+/// the surface seen at the first hit.
+pub struct Sample {
+    pub direct: Color,
+    pub indirect: Color,
+    pub first_hit: Option<FirstHit>,
+}
+
+pub struct FirstHit {
+    pub albedo: Color,
+    pub normal: Color,
+    pub point: Point3<f64>,
+    pub object_id: usize,
+    pub material_id: usize,
+}
+
+/// Traces a camera ray through the scene. Light reaching the camera after at most one
+/// scattering event counts as direct, everything else as indirect.
+///
+/// The medium the path is in is tracked so that light is absorbed inside objects, or scattered
+/// in a random walk through them when the medium scatters. Media do not nest: leaving an
+/// object always leads back into empty space.
+///
+/// When a path first hits a dispersive material, it picks a single wavelength to carry from
+/// then on and is weighted by the color of that wavelength, so the average over many paths
+/// reconstructs RGB.
+pub fn trace<T: Hitable + ?Sized>(ray: &Ray, world: &T) -> Sample {
+    let mut sample = Sample { direct: Color::black(), indirect: Color::black(), first_hit: None };
+    let mut throughput = Color::white();
+    let mut ray = Ray::new(ray.origin, ray.direction, ray.time);
+    let mut medium: Option<Medium> = None;
+
+    for depth in 0..MAX_DEPTH {
+        let hit = match medium {
+            Some(ref medium) if medium.scatters() => {
+                let extinction = medium.extinction();
+                let mut channels = [throughput.r, throughput.g, throughput.b];
+                let walk = walk_medium(
+                    &mut ray,
+                    world,
+                    medium,
+                    &mut channels,
+                    &[extinction.r, extinction.g, extinction.b],
+                    &[medium.scattering.r, medium.scattering.g, medium.scattering.b],
+                );
+                throughput = Color::new(channels[0], channels[1], channels[2]);
+                match walk {
+                    Ok(hit) => hit,
+                    Err(Absorbed) => break,
+                }
+            }
+            _ => world.hit(&ray, 0.001, f64::max_value()),
+        };
+
+        if let Some(rec) = hit {
+            if let Some(ref medium) = medium {
+                if !medium.scatters() {
+                    throughput = throughput * medium.transmittance(rec.t * ray.direction.magnitude());
+                }
+            }
+
+            if depth == 0 {
+                sample.first_hit = Some(first_hit(&rec));
+            }
+
+            sample.add(depth, throughput * rec.material.emitted(&rec));
+
+            if ray.wavelength.is_none() && rec.material.is_dispersive() {
+                let wavelength = spectrum::sample_wavelength();
+                throughput = throughput * spectrum::wavelength_weight(wavelength);
+                ray.wavelength = Some(wavelength);
+            }
+
+            if let Some((mut scattered, attenuation)) = rec.material.scatter(&ray, &rec) {
+                if rec.leaks(scattered.direction) {
+                    break;
+                }
+                scattered.wavelength = ray.wavelength;
+                update_medium(&mut medium, &ray, &scattered, &rec);
+                throughput = throughput * attenuation;
+                ray = scattered;
+            } else {
+                break;
+            }
+        } else {
+            sample.add(depth, throughput * background(&ray));
+            break;
+        }
+    }
+
+    sample
+}
+
+/// Traces a camera ray carrying a set of wavelengths instead of RGB. Colors of materials,
+/// media and the background are upsampled to spectra, and the radiance reaching the camera is
+/// converted back to RGB through XYZ.
+///
+/// Dispersive materials refract each wavelength in a different direction, so from the first
+/// one on the path only follows its hero wavelength.
+pub fn trace_spectral<T: Hitable + ?Sized>(ray: &Ray, world: &T) -> Sample {
+    let mut sample = Sample { direct: Color::black(), indirect: Color::black(), first_hit: None };
+    let wavelengths = SampledWavelengths::sample();
+    let mut throughput = Spectrum::constant(1.0);
+    let mut ray = Ray::new(ray.origin, ray.direction, ray.time);
+    let mut medium: Option<Medium> = None;
+
+    for depth in 0..MAX_DEPTH {
+        let hit = match medium {
+            Some(ref medium) if medium.scatters() => {
+                let extinction = Spectrum::from_rgb(medium.extinction(), &wavelengths);
+                let scattering = Spectrum::from_rgb(medium.scattering, &wavelengths);
+                match walk_medium(&mut ray, world, medium, &mut throughput.values, &extinction.values, &scattering.values) {
+                    Ok(hit) => hit,
+                    Err(Absorbed) => break,
+                }
+            }
+            _ => world.hit(&ray, 0.001, f64::max_value()),
+        };
+
+        if let Some(rec) = hit {
+            if let Some(ref medium) = medium {
+                if !medium.scatters() {
+                    let distance = rec.t * ray.direction.magnitude();
+                    let absorption = Spectrum::from_rgb(medium.absorption, &wavelengths);
+                    throughput = throughput * absorption.map(|a| (-a * distance).exp());
+                }
+            }
+
+            if depth == 0 {
+                sample.first_hit = Some(first_hit(&rec));
+            }
+
+            let emitted = throughput * rec.material.emitted_spectrum(&rec, &wavelengths);
+            sample.add(depth, emitted.to_rgb(&wavelengths));
+
+            if ray.wavelength.is_none() && rec.material.is_dispersive() {
+                throughput.terminate_secondary();
+                ray.wavelength = Some(wavelengths.hero());
+            }
+
+            if let Some((mut scattered, attenuation)) = rec.material.scatter(&ray, &rec) {
+                if rec.leaks(scattered.direction) {
+                    break;
+                }
+                scattered.wavelength = ray.wavelength;
+                update_medium(&mut medium, &ray, &scattered, &rec);
+                throughput = throughput * Spectrum::from_rgb(attenuation, &wavelengths);
+                ray = scattered;
+            } else {
+                break;
+            }
+        } else {
+            let radiance = throughput * Spectrum::from_rgb(background(&ray), &wavelengths);
+            sample.add(depth, radiance.to_rgb(&wavelengths));
+            break;
+        }
+    }
+
+    sample
+}
+
+impl Sample {
+    /// Adds light that reached the camera from a path vertex at `depth`.
+    fn add(&mut self, depth: u32, radiance: Color) {
+        if depth <= 1 {
+            self.direct += radiance;
+        } else {
+            self.indirect += radiance;
+        }
+    }
+}
+
+fn first_hit(rec: &HitRecord) -> FirstHit {
+    FirstHit {
+        albedo: rec.material.albedo(rec),
+        normal: Color::new(rec.normal.x, rec.normal.y, rec.normal.z),
+        point: rec.p,
+        object_id: rec.object_id,
+        material_id: rec.material_id,
+    }
+}
+
+/// A path that scattered inside a medium more often than it is followed, which counts as
+/// absorbed. Ending it black is slightly biased, treating it as a miss would add light.
+#[derive(Debug)]
+struct Absorbed;
+
+/// Follows a path through a scattering medium until it reaches a surface, updating `ray` at
+/// every scattering event, or `None` if it escapes. The throughput and the coefficients of the
+/// medium are given per channel, which are the color channels or the wavelengths of the path.
+///
+/// Distances are sampled with the extinction of a randomly picked channel and weighted by
+/// the average density over all channels, so that channels with different mean free paths
+/// are all sampled well (the spectral MIS of Wilkie et al. 2014).
+fn walk_medium<T: Hitable + ?Sized>(
+    ray: &mut Ray,
+    world: &T,
+    medium: &Medium,
+    throughput: &mut [f64],
+    extinction: &[f64],
+    scattering: &[f64],
+) -> Result<Option<HitRecord>, Absorbed> {
+    let channels = throughput.len();
+    for _ in 0..MAX_VOLUME_BOUNCES {
+        let rec = match world.hit(ray, 0.001, f64::max_value()) {
+            Some(rec) => rec,
+            None => return Ok(None),
+        };
+        let distance = rec.t * ray.direction.magnitude();
+
+        let channel = ((random::<f64>() * channels as f64) as usize).min(channels - 1);
+        let t = -(1.0 - random::<f64>()).ln() / extinction[channel];
+        if t >= distance {
+            let probability = extinction.iter().map(|e| (-e * distance).exp()).sum::<f64>() / channels as f64;
+            for (value, e) in throughput.iter_mut().zip(extinction) {
+                *value *= (-e * distance).exp() / probability;
+            }
+            return Ok(Some(rec));
+        }
+
+        let density = extinction.iter().map(|e| e * (-e * t).exp()).sum::<f64>() / channels as f64;
+        for ((value, e), s) in throughput.iter_mut().zip(extinction).zip(scattering) {
+            *value *= s * (-e * t).exp() / density;
+        }
+
+        let direction = ray.direction.normalize();
+        let mut scattered = Ray::new(ray.origin + t * direction, medium.sample_direction(direction), ray.time);
+        scattered.wavelength = ray.wavelength;
+        *ray = scattered;
+    }
+
+    Err(Absorbed)
+}
+
+/// Moves the path into the interior of the object when it was transmitted into it, and back
+/// out into empty space when transmitted out.
+fn update_medium(medium: &mut Option<Medium>, ray: &Ray, scattered: &Ray, rec: &HitRecord) {
+    let incoming = ray.direction.dot(rec.geometric_normal);
+    if incoming * scattered.direction.dot(rec.geometric_normal) > 0.0 {
+        *medium = if incoming < 0.0 { rec.material.interior().cloned() } else { None };
+    }
+}
+
+fn background(ray: &Ray) -> Color {
+    let unit_direction = ray.direction.normalize();
+    let t = 0.5 * (unit_direction.y + 1.0);
+    (1.0 - t) * Color::white() + t * Color::new(0.5, 0.7, 1.0)
+}
+
+/// Per pixel running sums of the samples. Geometric channels are only averaged over the
+/// samples that hit something, ids come from the first sample that hit.
+struct Accumulator {
+    direct: Color,
+    indirect: Color,
+    luminance_squared: f64,
+    albedo: Color,
+    normal: Color,
+    position: Color,
+    depth: f64,
+    hits: u32,
+    object_id: usize,
+    material_id: usize,
+}
+
+impl Accumulator {
+    fn new() -> Accumulator {
+        Accumulator {
+            direct: Color::black(),
+            indirect: Color::black(),
+            luminance_squared: 0.0,
+            albedo: Color::black(),
+            normal: Color::black(),
+            position: Color::black(),
+            depth: 0.0,
+            hits: 0,
+            object_id: 0,
+            material_id: 0,
+        }
+    }
+
+    fn add(&mut self, sample: &Sample, camera: &Camera) {
+        self.direct += sample.direct;
+        self.indirect += sample.indirect;
+        self.luminance_squared += (sample.direct + sample.indirect).luminance().powi(2);
+
+        if let Some(ref hit) = sample.first_hit {
+            if self.hits == 0 {
+                self.object_id = hit.object_id;
+                self.material_id = hit.material_id;
+            }
+            self.albedo += hit.albedo;
+            self.normal += hit.normal;
+            self.position += Color::new(hit.point.x, hit.point.y, hit.point.z);
+            self.depth += camera.depth(hit.point);
+            self.hits += 1;
+        }
+    }
+
+    fn write(&self, film: &mut Film, x: u32, y: u32, samples: u32) {
+        let ns = samples as f64;
+        let hits = self.hits.max(1) as f64;
+
+        let mean = (self.direct + self.indirect) / ns;
+        let variance = (self.luminance_squared / ns - mean.luminance().powi(2)).max(0.0) / ns;
+
+        film.set(Channel::Beauty, x, y, mean);
+        film.set(Channel::Variance, x, y, variance * Color::white());
+        film.set(Channel::Direct, x, y, self.direct / ns);
+        film.set(Channel::Indirect, x, y, self.indirect / ns);
+        film.set(Channel::Albedo, x, y, self.albedo / ns);
+        film.set(Channel::Normal, x, y, self.normal / hits);
+        film.set(Channel::Position, x, y, self.position / hits);
+        film.set(Channel::Depth, x, y, (self.depth / hits) * Color::white());
+        film.set(Channel::ObjectId, x, y, self.object_id as f64 * Color::white());
+        film.set(Channel::MaterialId, x, y, self.material_id as f64 * Color::white());
+    }
+}
+
+/// Renders the world with `ns` rays per pixel into a film that has the given channels. Paths
+/// carry wavelengths instead of RGB if `spectral` is set.
+pub fn render(camera: Arc<Camera>, world: Arc<Box<Hitable>>, nx: u32, ny: u32, ns: u32, aovs: &[Channel], spectral: bool) -> Film {
+    let cpus = ::num_cpus::get();
+
+    let arc_film = Arc::new(Mutex::new(Film::new(nx, ny, aovs)));
+    let mut threads = Vec::new();
+    let y_counter = Arc::new(Mutex::new(0));
+
+    for _ in 0..cpus {
+        let arc_film = arc_film.clone();
+        let camera = camera.clone();
+        let y_counter = y_counter.clone();
+        let world = world.clone();
+
+        threads.push(thread::spawn(move || {
+            let camera = &camera;
+            let world: &Hitable = &**world;
+            let mut row = Vec::with_capacity(nx as usize);
+
+            loop {
+                let y = get_and_increment(&y_counter);
+                if y >= ny {
+                    break;
+                }
+
+                print!("\r{}/{}", y + 1, ny);
+                ::std::io::stdout().flush().expect("Could not flush stdout");
+
+                row.clear();
+                for x in 0..nx {
+                    let mut acc = Accumulator::new();
+                    for _ in 0..ns {
+                        let (u, v) = film_coordinates(x as f64 + random::<f64>(), y as f64 + random::<f64>(), nx, ny);
+
+                        if let Some(ray) = camera.get_ray(u, v) {
+                            let sample = if spectral { trace_spectral(&ray, world) } else { trace(&ray, world) };
+                            acc.add(&sample, &**camera);
+                        }
+                    }
+
+                    row.push(acc);
+                }
+
+                let mut film = arc_film.lock().expect("could not lock film");
+                for (x, acc) in row.iter().enumerate() {
+                    acc.write(&mut film, x as u32, y, ns);
+                }
+            }
+        }));
+    }
+
+    for thread in threads {
+        let _ = thread.join();
+    }
+
+    match Arc::try_unwrap(arc_film) {
+        Ok(film) => film.into_inner().expect("could not lock film"),
+        Err(_) => panic!("render threads still hold the film"),
+    }
+}
+
+fn get_and_increment(counter: &Arc<Mutex<u32>>) -> u32 {
+    let mut shared_y = counter.lock().expect("locking counter failed");
+    let value = *shared_y;
+    *shared_y = value + 1;
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Point3, vec3};
+    use material::Lambertian;
+    use sphere::Sphere;
+
+    const PATHS: usize = 20_000;
+
+    /// Mean throughput per channel of paths starting at the center of a unit sphere filled
+    /// with `medium`, and the number of them that were absorbed.
+    fn walk_from_center(medium: &Medium) -> ([f64; 3], usize) {
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Box::new(Color::white()))));
+        let extinction = medium.extinction();
+        let scattering = medium.scattering;
+        let (mut sum, mut absorbed) = ([0.0; 3], 0);
+        for _ in 0..PATHS {
+            let mut ray = Ray::new(Point3::new(0.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0), 0.0);
+            let mut throughput = [1.0; 3];
+            match walk_medium(&mut ray, &sphere, medium, &mut throughput,
+                              &[extinction.r, extinction.g, extinction.b], &[scattering.r, scattering.g, scattering.b]) {
+                // Paths scattered within the hit epsilon of the surface miss it on the way out.
+                Ok(_) => {
+                    for (s, t) in sum.iter_mut().zip(&throughput) {
+                        *s += t;
+                    }
+                }
+                Err(Absorbed) => absorbed += 1,
+            }
+        }
+        let n = PATHS as f64;
+        ([sum[0] / n, sum[1] / n, sum[2] / n], absorbed)
+    }
+
+    #[test]
+    fn scattering_without_absorption_conserves_energy() {
+        let medium = Medium::scattering(Color::white(), Color::new(0.3, 0.25, 0.2));
+        let (mean, absorbed) = walk_from_center(&medium);
+        assert_eq!(absorbed, 0);
+        for m in &mean {
+            assert!((m - 1.0).abs() < 0.05, "mean throughput {:?} should be one", mean);
+        }
+    }
+
+    #[test]
+    fn absorption_matches_transmittance() {
+        // A tiny amount of scattering makes the walk handle the medium.
+        let mut medium = Medium::new(Color::new(0.5, 1.0, 2.0));
+        medium.scattering = Color::new(1e-9, 1e-9, 1e-9);
+        let expected = medium.transmittance(1.0);
+        let (mean, _) = walk_from_center(&medium);
+        for (m, e) in mean.iter().zip(&[expected.r, expected.g, expected.b]) {
+            assert!((m - e).abs() < 0.02, "mean throughput {:?} should be {:?}", mean, (expected.r, expected.g, expected.b));
+        }
+    }
+
+    #[test]
+    fn walks_that_never_leave_are_absorbed() {
+        let medium = Medium::scattering(Color::white(), Color::new(1e-4, 1e-4, 1e-4));
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Box::new(Color::white()))));
+        let mut ray = Ray::new(Point3::new(0.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0), 0.0);
+        let e = medium.extinction().r;
+        let result = walk_medium(&mut ray, &sphere, &medium, &mut [1.0; 3], &[e; 3], &[e; 3]);
+        assert!(result.is_err());
+    }
+}
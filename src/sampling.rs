@@ -0,0 +1,137 @@
+use cgmath::{vec3, Vector3};
+use cgmath::prelude::*;
+use rand::random;
+use std::f64::consts::PI;
+
+/// Orthonormal shading frame around a surface normal. Directions are converted to a local
+/// space where the normal is `z`, and `x` runs along the tangent.
+pub struct Onb {
+    pub tangent: Vector3<f64>,
+    pub bitangent: Vector3<f64>,
+    pub normal: Vector3<f64>,
+}
+
+impl Onb {
+    /// Frame whose tangent follows the lines of latitude around the world `y` axis, which
+    /// lines anisotropic highlights up the way a lathe would.
+    pub fn from_normal(normal: Vector3<f64>) -> Onb {
+        let around_y = vec3(0.0, 1.0, 0.0).cross(normal);
+        let tangent = if around_y.magnitude2() > 1e-12 {
+            around_y.normalize()
+        } else {
+            vec3(1.0, 0.0, 0.0).cross(normal).normalize()
+        };
+        Onb { tangent, bitangent: normal.cross(tangent), normal }
+    }
+
+    pub fn to_local(&self, v: Vector3<f64>) -> Vector3<f64> {
+        vec3(v.dot(self.tangent), v.dot(self.bitangent), v.dot(self.normal))
+    }
+
+    pub fn to_world(&self, v: Vector3<f64>) -> Vector3<f64> {
+        v.x * self.tangent + v.y * self.bitangent + v.z * self.normal
+    }
+}
+
+/// Samples a direction in the upper hemisphere with probability proportional to its cosine
+/// with the normal, by projecting a uniform point on the unit disc up onto the hemisphere.
+pub fn sample_cosine_hemisphere() -> Vector3<f64> {
+    let r = random::<f64>().sqrt();
+    let phi = 2.0 * PI * random::<f64>();
+    let (x, y) = (r * phi.cos(), r * phi.sin());
+    vec3(x, y, (1.0 - x * x - y * y).max(0.0).sqrt())
+}
+
+/// Samples a point uniformly inside the unit ball by rejection.
+pub fn random_in_unit_sphere() -> Vector3<f64> {
+    loop {
+        let v = 2.0 * vec3(random::<f64>(), random::<f64>(), random::<f64>()) - vec3(1.0, 1.0, 1.0);
+        if v.magnitude2() < 1.0 {
+            return v;
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Pearson's chi-square test of `observed` counts against `expected` ones at a
+    /// significance level of 0.001. Bins expecting fewer than five samples are merged with the
+    /// following ones, as the test is unreliable for them.
+    pub fn chi_square_test(observed: &[u64], expected: &[f64]) {
+        let total: u64 = observed.iter().sum();
+        let (mut statistic, mut bins) = (0.0, 0);
+        let (mut pooled_observed, mut pooled_expected) = (0.0, 0.0);
+        for (&o, &e) in observed.iter().zip(expected) {
+            assert!(e > 0.0 || o == 0, "{} samples in a bin that should be empty", o);
+            pooled_observed += o as f64;
+            pooled_expected += e;
+            if pooled_expected >= 5.0 {
+                statistic += (pooled_observed - pooled_expected).powi(2) / pooled_expected;
+                bins += 1;
+                pooled_observed = 0.0;
+                pooled_expected = 0.0;
+            }
+        }
+        if pooled_expected > 0.0 {
+            statistic += (pooled_observed - pooled_expected).powi(2) / pooled_expected;
+            bins += 1;
+        }
+        assert!(bins > 1 && total > 0);
+
+        // Wilson-Hilferty approximation of the quantile of the chi-square distribution.
+        let dof = (bins - 1) as f64;
+        let z = 3.09;
+        let critical = dof * (1.0 - 2.0 / (9.0 * dof) + z * (2.0 / (9.0 * dof)).sqrt()).powi(3);
+        assert!(statistic < critical, "chi-square {} exceeds {} with {} degrees of freedom", statistic, critical, dof);
+    }
+
+    const SAMPLES: usize = 1_000_000;
+
+    fn phi_bin(v: Vector3<f64>, bins: usize) -> usize {
+        let phi = v.y.atan2(v.x) + PI;
+        ((phi / (2.0 * PI) * bins as f64) as usize).min(bins - 1)
+    }
+
+    #[test]
+    fn cosine_hemisphere_is_cosine_distributed() {
+        let (z_bins, phi_bins) = (20, 20);
+        let mut observed = vec![0; z_bins * phi_bins];
+        for _ in 0..SAMPLES {
+            let v = sample_cosine_hemisphere();
+            assert!((v.magnitude() - 1.0).abs() < 1e-9 && v.z >= 0.0);
+            let z = ((v.z * z_bins as f64) as usize).min(z_bins - 1);
+            observed[z * phi_bins + phi_bin(v, phi_bins)] += 1;
+        }
+
+        // The density is cos(theta) / pi over solid angle, which is z dz dphi / pi.
+        let mut expected = Vec::new();
+        for z in 0..z_bins {
+            let (z0, z1) = (z as f64 / z_bins as f64, (z + 1) as f64 / z_bins as f64);
+            for _ in 0..phi_bins {
+                let fraction = (z1 * z1 - z0 * z0) / 2.0 * (2.0 / phi_bins as f64);
+                expected.push(fraction * SAMPLES as f64);
+            }
+        }
+        chi_square_test(&observed, &expected);
+    }
+
+    #[test]
+    fn unit_sphere_is_uniform() {
+        let (r_bins, z_bins, phi_bins) = (8, 8, 8);
+        let mut observed = vec![0; r_bins * z_bins * phi_bins];
+        for _ in 0..SAMPLES {
+            let v = random_in_unit_sphere();
+            let r = v.magnitude();
+            assert!(r < 1.0);
+            // Uniform in the ball means r^3, z / r and phi are uniform and independent.
+            let rb = ((r * r * r * r_bins as f64) as usize).min(r_bins - 1);
+            let zb = (((v.z / r + 1.0) / 2.0 * z_bins as f64) as usize).min(z_bins - 1);
+            observed[(rb * z_bins + zb) * phi_bins + phi_bin(v, phi_bins)] += 1;
+        }
+
+        let expected = vec![SAMPLES as f64 / observed.len() as f64; observed.len()];
+        chi_square_test(&observed, &expected);
+    }
+}
@@ -0,0 +1,378 @@
+use color::Color;
+use rand::random;
+use std::ops;
+use std::sync::OnceLock;
+
+/// Range of visible wavelengths in nanometers.
+pub const WAVELENGTH_MIN: f64 = 380.0;
+pub const WAVELENGTH_MAX: f64 = 780.0;
+
+/// Number of wavelengths carried by each path in spectral mode.
+pub const SPECTRUM_SAMPLES: usize = 4;
+
+/// Samples a visible wavelength uniformly.
+pub fn sample_wavelength() -> f64 {
+    WAVELENGTH_MIN + random::<f64>() * (WAVELENGTH_MAX - WAVELENGTH_MIN)
+}
+
+/// CIE 1931 color matching functions, using the multi-lobe fit of Wyman, Sloan and Shirley.
+pub fn xyz(wavelength: f64) -> (f64, f64, f64) {
+    let g = |mu: f64, sigma1: f64, sigma2: f64| {
+        let sigma = if wavelength < mu { sigma1 } else { sigma2 };
+        let x = (wavelength - mu) / sigma;
+        (-0.5 * x * x).exp()
+    };
+
+    let x = 1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2);
+    let y = 0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1);
+    let z = 1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8);
+    (x, y, z)
+}
+
+/// Converts CIE XYZ to linear sRGB.
+pub fn xyz_to_rgb(x: f64, y: f64, z: f64) -> Color {
+    Color::new(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    )
+}
+
+/// Color a path carrying a single wavelength contributes to the image, for wavelengths
+/// sampled uniformly. Negative components of colors outside the sRGB gamut are clipped, and
+/// the result is scaled so that averaging over all wavelengths gives back white.
+pub fn wavelength_weight(wavelength: f64) -> Color {
+    static NORMALIZATION: OnceLock<Color> = OnceLock::new();
+    let normalization = NORMALIZATION.get_or_init(|| {
+        const STEPS: u32 = 1000;
+        let mut sum = Color::black();
+        for i in 0..STEPS {
+            let wavelength = WAVELENGTH_MIN + (i as f64 + 0.5) / STEPS as f64 * (WAVELENGTH_MAX - WAVELENGTH_MIN);
+            sum += clipped_rgb(wavelength);
+        }
+        sum / STEPS as f64
+    });
+
+    let rgb = clipped_rgb(wavelength);
+    Color::new(rgb.r / normalization.r, rgb.g / normalization.g, rgb.b / normalization.b)
+}
+
+/// Color of white light reflected by a surface with the given reflectance at every wavelength,
+/// integrated over the visible range. This is how RGB paths see materials that only have a
+/// spectral description.
+pub fn reflectance_to_rgb<F: Fn(f64) -> f64>(reflectance: F) -> Color {
+    const STEPS: u32 = 32;
+    let mut sum = Color::black();
+    for i in 0..STEPS {
+        let wavelength = WAVELENGTH_MIN + (i as f64 + 0.5) / STEPS as f64 * (WAVELENGTH_MAX - WAVELENGTH_MIN);
+        sum += reflectance(wavelength) * wavelength_weight(wavelength);
+    }
+    sum / STEPS as f64
+}
+
+fn clipped_rgb(wavelength: f64) -> Color {
+    let (x, y, z) = xyz(wavelength);
+    let rgb = xyz_to_rgb(x, y, z);
+    Color::new(rgb.r.max(0.0), rgb.g.max(0.0), rgb.b.max(0.0))
+}
+
+/// Wavelengths carried by a path in spectral mode. The first one, the hero wavelength, is
+/// sampled uniformly and the rest are spaced evenly after it, wrapping around the visible
+/// range, so that together they cover the spectrum with little correlation.
+#[derive(Clone, Copy)]
+pub struct SampledWavelengths {
+    pub lambda: [f64; SPECTRUM_SAMPLES],
+}
+
+impl SampledWavelengths {
+    pub fn sample() -> SampledWavelengths {
+        let range = WAVELENGTH_MAX - WAVELENGTH_MIN;
+        let hero = random::<f64>() * range;
+        let mut lambda = [0.0; SPECTRUM_SAMPLES];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            *l = WAVELENGTH_MIN + (hero + i as f64 * range / SPECTRUM_SAMPLES as f64) % range;
+        }
+        SampledWavelengths { lambda }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+}
+
+/// Values of a spectral quantity at the wavelengths of a path.
+#[derive(Clone, Copy)]
+pub struct Spectrum {
+    pub values: [f64; SPECTRUM_SAMPLES],
+}
+
+impl Spectrum {
+    pub fn constant(value: f64) -> Spectrum {
+        Spectrum { values: [value; SPECTRUM_SAMPLES] }
+    }
+
+    /// Upsamples an RGB color to a smooth spectrum with Smits' method.
+    pub fn from_rgb(color: Color, wavelengths: &SampledWavelengths) -> Spectrum {
+        let mut values = [0.0; SPECTRUM_SAMPLES];
+        for (value, &lambda) in values.iter_mut().zip(wavelengths.lambda.iter()) {
+            *value = smits(color, lambda);
+        }
+        Spectrum { values }
+    }
+
+    pub fn map<F: Fn(f64) -> f64>(&self, f: F) -> Spectrum {
+        let mut values = self.values;
+        for value in &mut values {
+            *value = f(*value);
+        }
+        Spectrum { values }
+    }
+
+    /// Keeps only the hero wavelength, for paths through materials that scatter each
+    /// wavelength differently. The hero is scaled up to account for the others it stands in for.
+    pub fn terminate_secondary(&mut self) {
+        self.values[0] *= SPECTRUM_SAMPLES as f64;
+        for value in &mut self.values[1..] {
+            *value = 0.0;
+        }
+    }
+
+    /// Estimates the color of the spectrum by integrating it against the color matching
+    /// functions to XYZ, which is then converted to the output color space.
+    pub fn to_rgb(self, wavelengths: &SampledWavelengths) -> Color {
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        for (&value, &lambda) in self.values.iter().zip(wavelengths.lambda.iter()) {
+            let (cx, cy, cz) = xyz(lambda);
+            x += value * cx;
+            y += value * cy;
+            z += value * cz;
+        }
+        let scale = (WAVELENGTH_MAX - WAVELENGTH_MIN) / SPECTRUM_SAMPLES as f64;
+        output_rgb(x * scale, y * scale, z * scale)
+    }
+}
+
+impl ops::Mul<Spectrum> for Spectrum {
+    type Output = Spectrum;
+
+    fn mul(self, rhs: Spectrum) -> Spectrum {
+        let mut values = self.values;
+        for (value, r) in values.iter_mut().zip(rhs.values.iter()) {
+            *value *= r;
+        }
+        Spectrum { values }
+    }
+}
+
+impl ops::AddAssign<Spectrum> for Spectrum {
+    fn add_assign(&mut self, rhs: Spectrum) {
+        for (value, r) in self.values.iter_mut().zip(rhs.values.iter()) {
+            *value += r;
+        }
+    }
+}
+
+/// Spectral power distribution of a light source. Build it with `Spd::blackbody` or
+/// `Spd::tabulated`, which check their inputs.
+pub enum Spd {
+    /// Planck's law for the given temperature in kelvins, normalized to one at its peak.
+    Blackbody(f64),
+    /// Pairs of wavelength in nanometers and power, sorted by wavelength and interpolated
+    /// linearly. The power is zero outside the table.
+    Tabulated(Vec<(f64, f64)>),
+}
+
+impl Spd {
+    pub fn blackbody(temperature: f64) -> Spd {
+        assert!(temperature > 0.0 && temperature.is_finite(), "the temperature of a blackbody must be positive");
+        Spd::Blackbody(temperature)
+    }
+
+    /// Spectrum interpolated from `(wavelength, power)` pairs, given in any order.
+    pub fn tabulated(mut table: Vec<(f64, f64)>) -> Spd {
+        assert!(table.iter().all(|&(l, v)| l.is_finite() && v.is_finite()), "spectral power tables must be finite");
+        table.sort_by(|a, b| a.0.partial_cmp(&b.0).expect("NaN wavelength"));
+        Spd::Tabulated(table)
+    }
+
+    pub fn value(&self, wavelength: f64) -> f64 {
+        match *self {
+            Spd::Blackbody(temperature) => {
+                // Wien's displacement law gives the peak wavelength.
+                let peak = 2.897_771_955e6 / temperature;
+                planck(wavelength, temperature) / planck(peak, temperature)
+            }
+            Spd::Tabulated(ref table) => {
+                let i = match table.iter().position(|&(l, _)| l >= wavelength) {
+                    Some(i) => i,
+                    None => return 0.0,
+                };
+                let (l1, v1) = table[i];
+                if l1 == wavelength {
+                    return v1;
+                }
+                if i == 0 {
+                    return 0.0;
+                }
+                let (l0, v0) = table[i - 1];
+                v0 + (v1 - v0) * (wavelength - l0) / (l1 - l0)
+            }
+        }
+    }
+
+    /// Color of the light in the output color space, for rendering in RGB.
+    pub fn to_rgb(&self) -> Color {
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        let mut lambda = WAVELENGTH_MIN + 0.5;
+        while lambda < WAVELENGTH_MAX {
+            let value = self.value(lambda);
+            let (cx, cy, cz) = xyz(lambda);
+            x += value * cx;
+            y += value * cy;
+            z += value * cz;
+            lambda += 1.0;
+        }
+        output_rgb(x, y, z)
+    }
+}
+
+/// Spectral radiance of a blackbody, up to a constant factor.
+fn planck(wavelength: f64, temperature: f64) -> f64 {
+    const C2: f64 = 1.438_776_877e-2;
+    let l = wavelength * 1e-9;
+    1.0 / (l.powi(5) * ((C2 / (l * temperature)).exp() - 1.0))
+}
+
+/// Converts XYZ integrated over the visible range to the output color space. The result is
+/// scaled so that a spectrum with constant value one has a luminance of one, and white
+/// balanced so that it is white.
+fn output_rgb(x: f64, y: f64, z: f64) -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    let white = WHITE.get_or_init(|| {
+        let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+        let mut lambda = WAVELENGTH_MIN + 0.5;
+        while lambda < WAVELENGTH_MAX {
+            let (cx, cy, cz) = xyz(lambda);
+            x += cx;
+            y += cy;
+            z += cz;
+            lambda += 1.0;
+        }
+        xyz_to_rgb(x, y, z)
+    });
+
+    let rgb = xyz_to_rgb(x, y, z);
+    Color::new(rgb.r / white.r, rgb.g / white.g, rgb.b / white.b)
+}
+
+/// Number of bins of the Smits basis spectra, which are 34 nm wide from 380 nm on. Smits'
+/// tables end at 720 nm, the last two bins extend them with their last values to cover all
+/// sampled wavelengths.
+const SMITS_BINS: usize = 12;
+const SMITS_MAX: f64 = 788.0;
+
+const SMITS_WHITE: [f64; SMITS_BINS] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f64; SMITS_BINS] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f64; SMITS_BINS] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959, 0.9959, 0.9959];
+const SMITS_YELLOW: [f64; SMITS_BINS] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840, 0.9840, 0.9840];
+const SMITS_RED: [f64; SMITS_BINS] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f64; SMITS_BINS] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025, 0.0025, 0.0025];
+const SMITS_BLUE: [f64; SMITS_BINS] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496, 0.0496, 0.0496];
+
+/// Value at `wavelength` of the spectrum Smits (1999) constructs for an RGB color: white plus
+/// the secondary and primary colors needed to reach it, from the smallest component up.
+fn smits(color: Color, wavelength: f64) -> f64 {
+    let bin = ((wavelength - WAVELENGTH_MIN) / (SMITS_MAX - WAVELENGTH_MIN) * SMITS_BINS as f64) as usize;
+    let i = bin.min(SMITS_BINS - 1);
+    let (r, g, b) = (color.r, color.g, color.b);
+
+    if r <= g && r <= b {
+        let rest = if g <= b {
+            (g - r) * SMITS_CYAN[i] + (b - g) * SMITS_BLUE[i]
+        } else {
+            (b - r) * SMITS_CYAN[i] + (g - b) * SMITS_GREEN[i]
+        };
+        r * SMITS_WHITE[i] + rest
+    } else if g <= r && g <= b {
+        let rest = if r <= b {
+            (r - g) * SMITS_MAGENTA[i] + (b - r) * SMITS_BLUE[i]
+        } else {
+            (b - g) * SMITS_MAGENTA[i] + (r - b) * SMITS_RED[i]
+        };
+        g * SMITS_WHITE[i] + rest
+    } else {
+        let rest = if r <= g {
+            (r - b) * SMITS_YELLOW[i] + (g - r) * SMITS_GREEN[i]
+        } else {
+            (g - b) * SMITS_YELLOW[i] + (r - g) * SMITS_RED[i]
+        };
+        b * SMITS_WHITE[i] + rest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_interpolate_between_wavelengths() {
+        // Given out of order, the table is sorted.
+        let spd = Spd::tabulated(vec![(600.0, 2.0), (400.0, 1.0), (500.0, 3.0)]);
+        assert_eq!(spd.value(399.0), 0.0);
+        assert_eq!(spd.value(400.0), 1.0);
+        assert!((spd.value(450.0) - 2.0).abs() < 1e-12);
+        assert_eq!(spd.value(500.0), 3.0);
+        assert!((spd.value(575.0) - 2.25).abs() < 1e-12);
+        assert_eq!(spd.value(600.0), 2.0);
+        assert_eq!(spd.value(601.0), 0.0);
+    }
+
+    #[test]
+    fn blackbodies_peak_at_one() {
+        let spd = Spd::blackbody(5000.0);
+        let peak = 2.897_771_955e6 / 5000.0;
+        assert!((spd.value(peak) - 1.0).abs() < 1e-12);
+        assert!(spd.value(peak - 50.0) < 1.0 && spd.value(peak + 50.0) < 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be positive")]
+    fn blackbodies_need_a_positive_temperature() {
+        Spd::blackbody(0.0);
+    }
+
+    #[test]
+    fn wavelength_weights_average_to_white() {
+        const STEPS: u32 = 4000;
+        let mut sum = Color::black();
+        for i in 0..STEPS {
+            sum += wavelength_weight(WAVELENGTH_MIN + (i as f64 + 0.5) / STEPS as f64 * (WAVELENGTH_MAX - WAVELENGTH_MIN));
+        }
+        let average = sum / STEPS as f64;
+        for &channel in &[average.r, average.g, average.b] {
+            assert!((channel - 1.0).abs() < 1e-3, "average weight is {:?}", (average.r, average.g, average.b));
+        }
+    }
+
+    #[test]
+    fn upsampled_colors_come_back() {
+        const HEROES: u32 = 2000;
+        let range = WAVELENGTH_MAX - WAVELENGTH_MIN;
+        for &color in &[Color::white(), Color::new(0.8, 0.2, 0.1), Color::new(0.1, 0.6, 0.3), Color::new(0.2, 0.3, 0.9)] {
+            // Heroes spread evenly over the first of the evenly spaced groups cover the range.
+            let mut sum = Color::black();
+            for i in 0..HEROES {
+                let hero = (i as f64 + 0.5) / HEROES as f64 * range / SPECTRUM_SAMPLES as f64;
+                let mut lambda = [0.0; SPECTRUM_SAMPLES];
+                for (j, l) in lambda.iter_mut().enumerate() {
+                    *l = WAVELENGTH_MIN + hero + j as f64 * range / SPECTRUM_SAMPLES as f64;
+                }
+                let wavelengths = SampledWavelengths { lambda };
+                sum += Spectrum::from_rgb(color, &wavelengths).to_rgb(&wavelengths);
+            }
+            let back = sum / HEROES as f64;
+            let error = (back.r - color.r).abs().max((back.g - color.g).abs()).max((back.b - color.b).abs());
+            assert!(error < 0.05, "{:?} came back as {:?}", (color.r, color.g, color.b), (back.r, back.g, back.b));
+        }
+    }
+}
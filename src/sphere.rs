@@ -1,16 +1,19 @@
 use aabb::AABB;
-use cgmath::Point3;
+use cgmath::{Point3, Vector3};
 use cgmath::prelude::*;
 use cgmath::vec3;
-use hitable::{Hitable, HitRecord};
+use hitable::{Hitable, HitRecord, next_object_id};
 use material::Material;
 use ray::Ray;
+use std::f64::consts::PI;
 use std::sync::Arc;
 
 pub struct Sphere {
     pub center: Point3<f64>,
     pub radius: f64,
     pub material: Arc<Material>,
+    object_id: usize,
+    material_id: usize,
 }
 
 impl Sphere {
@@ -18,6 +21,8 @@ impl Sphere {
         Sphere {
             center,
             radius,
+            object_id: next_object_id(),
+            material_id: material.id(),
             material,
         }
     }
@@ -33,14 +38,18 @@ impl Hitable for Sphere {
         if discriminant > 0.0 {
             let t = (-b - (b * b - a * c).sqrt()) / a;
             if t < t_max && t > t_min {
-                let pt = ray.point_at(t);
-                return Some(HitRecord::new(t, pt, (pt - self.center) / self.radius, &self.material));
+                let normal = (ray.point_at(t) - self.center) / self.radius;
+                let (dpdu, dpdv) = sphere_derivatives(normal, self.radius);
+                return Some(HitRecord::new(ray, t, normal, sphere_uv(normal), &self.material, self.object_id, self.material_id)
+                    .with_derivatives(dpdu, dpdv));
             }
 
             let t = (-b + (b * b - a * c).sqrt()) / a;
             if t < t_max && t > t_min {
-                let pt = ray.point_at(t);
-                return Some(HitRecord::new(t, pt, (pt - self.center) / self.radius, &self.material));
+                let normal = (ray.point_at(t) - self.center) / self.radius;
+                let (dpdu, dpdv) = sphere_derivatives(normal, self.radius);
+                return Some(HitRecord::new(ray, t, normal, sphere_uv(normal), &self.material, self.object_id, self.material_id)
+                    .with_derivatives(dpdu, dpdv));
             }
         }
         None
@@ -51,6 +60,22 @@ impl Hitable for Sphere {
     }
 }
 
+/// Texture coordinates of a point on the unit sphere. `u` runs around the `y` axis and `v`
+/// from the bottom pole to the top one.
+fn sphere_uv(normal: Vector3<f64>) -> (f64, f64) {
+    let theta = (-normal.y).clamp(-1.0, 1.0).acos();
+    let phi = (-normal.z).atan2(normal.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+/// Derivatives of the position with respect to the texture coordinates of `sphere_uv`.
+fn sphere_derivatives(normal: Vector3<f64>, radius: f64) -> (Vector3<f64>, Vector3<f64>) {
+    let sin_theta = (1.0 - normal.y * normal.y).max(1e-8).sqrt();
+    let dpdu = 2.0 * PI * radius * vec3(normal.z, 0.0, -normal.x);
+    let dpdv = PI * radius * vec3(-normal.y * normal.x / sin_theta, sin_theta, -normal.y * normal.z / sin_theta);
+    (dpdu, dpdv)
+}
+
 fn sphere_box(center: &Point3<f64>, radius: f64) -> AABB {
     let v = vec3(radius, radius, radius);
     AABB::new(center - v, center + v)
@@ -63,6 +88,8 @@ pub struct MovingSphere {
     time1: f64,
     pub radius: f64,
     pub material: Arc<Material>,
+    object_id: usize,
+    material_id: usize,
 }
 
 impl MovingSphere {
@@ -73,6 +100,8 @@ impl MovingSphere {
             time0,
             time1,
             radius,
+            object_id: next_object_id(),
+            material_id: material.id(),
             material,
         }
     }
@@ -93,14 +122,18 @@ impl Hitable for MovingSphere {
         if discriminant > 0.0 {
             let t = (-b - (b * b - a * c).sqrt()) / a;
             if t < t_max && t > t_min {
-                let pt = ray.point_at(t);
-                return Some(HitRecord::new(t, pt, (pt - center) / self.radius, &self.material));
+                let normal = (ray.point_at(t) - center) / self.radius;
+                let (dpdu, dpdv) = sphere_derivatives(normal, self.radius);
+                return Some(HitRecord::new(ray, t, normal, sphere_uv(normal), &self.material, self.object_id, self.material_id)
+                    .with_derivatives(dpdu, dpdv));
             }
 
             let t = (-b + (b * b - a * c).sqrt()) / a;
             if t < t_max && t > t_min {
-                let pt = ray.point_at(t);
-                return Some(HitRecord::new(t, pt, (pt - center) / self.radius, &self.material));
+                let normal = (ray.point_at(t) - center) / self.radius;
+                let (dpdu, dpdv) = sphere_derivatives(normal, self.radius);
+                return Some(HitRecord::new(ray, t, normal, sphere_uv(normal), &self.material, self.object_id, self.material_id)
+                    .with_derivatives(dpdu, dpdv));
             }
         }
         None
@@ -0,0 +1,66 @@
+use color::Color;
+use hitable::HitRecord;
+use material::{Material, next_material_id, RoughDielectric};
+use medium::Medium;
+use ray::Ray;
+use texture::ScalarTexture;
+
+/// Translucent material such as wax, marble, milk or skin. Light refracts into the object
+/// through a dielectric surface and takes a random walk through the scattering medium inside
+/// until it leaves again somewhere else, which softens details and makes thin parts glow.
+pub struct Subsurface {
+    color: Color,
+    boundary: RoughDielectric,
+    interior: Medium,
+    id: usize,
+}
+
+impl Subsurface {
+    /// Material that appears as `color` when thick, where light travels `mean_free_path` on
+    /// average between two scattering events.
+    pub fn new(color: Color, mean_free_path: Color, refraction_index: f64) -> Subsurface {
+        let albedo = Color::new(single_scattering_albedo(color.r), single_scattering_albedo(color.g), single_scattering_albedo(color.b));
+        Subsurface {
+            color,
+            boundary: RoughDielectric::new(refraction_index, Box::new(0.0)),
+            interior: Medium::scattering(albedo, mean_free_path),
+            id: next_material_id(),
+        }
+    }
+
+    pub fn with_roughness(mut self, roughness: Box<ScalarTexture>) -> Subsurface {
+        self.boundary = self.boundary.with_roughness(roughness);
+        self
+    }
+
+    /// Makes the medium scatter light forward (positive) or back (negative), see `Medium`.
+    pub fn with_anisotropy(mut self, anisotropy: f64) -> Subsurface {
+        self.interior = self.interior.with_anisotropy(anisotropy);
+        self
+    }
+}
+
+/// Albedo of a single scattering event for which a thick slab of the medium reflects
+/// `albedo` after all the bounces inside it, using the fit of Christensen and Burley (2015).
+fn single_scattering_albedo(albedo: f64) -> f64 {
+    let a = albedo.clamp(0.0, 1.0);
+    1.0 - (4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt()).powi(2)
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+        self.boundary.scatter(r_in, rec)
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.color
+    }
+
+    fn interior(&self) -> Option<&Medium> {
+        Some(&self.interior)
+    }
+
+    fn id(&self) -> usize {
+        self.id
+    }
+}
@@ -1,10 +1,23 @@
 use cgmath::Point3;
 use color::Color;
+use image;
+use std::path::Path;
 
 pub trait Texture: Sync + Send {
     fn value(&self, u: f64, v: f64, p: &Point3<f64>) -> Color;
 }
 
+/// Texture with a single value per point, for parameters such as roughness.
+pub trait ScalarTexture: Sync + Send {
+    fn value(&self, u: f64, v: f64, p: &Point3<f64>) -> f64;
+}
+
+impl ScalarTexture for f64 {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3<f64>) -> f64 {
+        *self
+    }
+}
+
 impl Texture for Color {
     fn value(&self, _u: f64, _v: f64, _p: &Point3<f64>) -> Color {
         *self
@@ -24,12 +37,108 @@ impl CheckerTexture {
 
 impl Texture for CheckerTexture {
     fn value(&self, u: f64, v: f64, p: &Point3<f64>) -> Color {
-        let sines = (10.0 * p.x).sin() * (10.0 * p.y).sin() * (10.0 * p.z).sin();
+        if is_odd_checker(p) {
+            self.odd.value(u, v, p)
+        } else {
+            self.even.value(u, v, p)
+        }
+    }
+}
+
+/// Checker pattern of two scalar textures, for masks and other parameters.
+pub struct ScalarCheckerTexture {
+    odd: Box<ScalarTexture>,
+    even: Box<ScalarTexture>,
+}
+
+impl ScalarCheckerTexture {
+    pub fn new(odd: Box<ScalarTexture>, even: Box<ScalarTexture>) -> ScalarCheckerTexture {
+        ScalarCheckerTexture { odd, even }
+    }
+}
 
-        if sines < 0.0 {
+impl ScalarTexture for ScalarCheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3<f64>) -> f64 {
+        if is_odd_checker(p) {
             self.odd.value(u, v, p)
         } else {
             self.even.value(u, v, p)
         }
     }
 }
+
+/// Scalar texture with values in `[0, 1]` stretched to `[min, max]`, for parameters measured
+/// in other units.
+pub struct RemapTexture {
+    texture: Box<ScalarTexture>,
+    min: f64,
+    max: f64,
+}
+
+impl RemapTexture {
+    pub fn new(texture: Box<ScalarTexture>, min: f64, max: f64) -> RemapTexture {
+        RemapTexture { texture, min, max }
+    }
+}
+
+impl ScalarTexture for RemapTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3<f64>) -> f64 {
+        self.min + (self.max - self.min) * self.texture.value(u, v, p)
+    }
+}
+
+fn is_odd_checker(p: &Point3<f64>) -> bool {
+    (10.0 * p.x).sin() * (10.0 * p.y).sin() * (10.0 * p.z).sin() < 0.0
+}
+
+/// Image mapped with texture coordinates, repeating outside `[0, 1]`. As a scalar texture
+/// it gives the luminance of the image.
+pub struct ImageTexture {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    pub fn open(path: &Path) -> image::ImageResult<ImageTexture> {
+        let img = image::open(path)?.to_rgb();
+        let (width, height) = img.dimensions();
+        // Images are stored with the gamma the film applies, undo it to get linear colors.
+        let linear = |c: u8| (c as f64 / 255.0).powi(2);
+        let pixels = img.pixels().map(|p| Color::new(linear(p.data[0]), linear(p.data[1]), linear(p.data[2]))).collect();
+        Ok(ImageTexture { width, height, pixels })
+    }
+
+    /// Opens the alpha channel of an image as a gray image, for use as an opacity mask.
+    /// Images without alpha are opaque.
+    pub fn open_alpha(path: &Path) -> image::ImageResult<ImageTexture> {
+        let img = image::open(path)?.to_rgba();
+        let (width, height) = img.dimensions();
+        let pixels = img.pixels().map(|p| Color::white() * (p.data[3] as f64 / 255.0)).collect();
+        Ok(ImageTexture { width, height, pixels })
+    }
+
+    /// Opens an image holding data rather than colors, such as a normal map, whose values are
+    /// used as stored.
+    pub fn open_data(path: &Path) -> image::ImageResult<ImageTexture> {
+        let img = image::open(path)?.to_rgb();
+        let (width, height) = img.dimensions();
+        let value = |c: u8| c as f64 / 255.0;
+        let pixels = img.pixels().map(|p| Color::new(value(p.data[0]), value(p.data[1]), value(p.data[2]))).collect();
+        Ok(ImageTexture { width, height, pixels })
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3<f64>) -> Color {
+        let x = ((u - u.floor()) * self.width as f64) as u32 % self.width;
+        let y = ((1.0 - (v - v.floor())) * self.height as f64) as u32 % self.height;
+        self.pixels[(y * self.width + x) as usize]
+    }
+}
+
+impl ScalarTexture for ImageTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3<f64>) -> f64 {
+        Texture::value(self, u, v, p).luminance()
+    }
+}
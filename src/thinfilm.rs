@@ -0,0 +1,127 @@
+use hitable::HitRecord;
+use std::f64::consts::PI;
+use std::ops;
+use texture::ScalarTexture;
+
+/// Thin transparent film on a surface, such as soap, oil on water or the coating of a lens.
+/// Light reflected off the top and the bottom of the film interferes, which makes the
+/// reflectance depend on the wavelength, the angle and the thickness of the film.
+pub struct ThinFilm {
+    /// Thickness of the film in nanometers.
+    thickness: Box<ScalarTexture>,
+    refraction_index: f64,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: Box<ScalarTexture>, refraction_index: f64) -> ThinFilm {
+        ThinFilm { thickness, refraction_index }
+    }
+
+    /// Thickness of the film at a hit, in nanometers.
+    pub fn thickness(&self, rec: &HitRecord) -> f64 {
+        self.thickness.value(rec.u, rec.v, &rec.p).max(0.0)
+    }
+
+    /// Fraction of unpolarized light of the given wavelength reflected by the film of the given
+    /// thickness, for light arriving at cosine `cosine` through a medium with index `outside`
+    /// onto a substrate with complex index `eta + i k`. A dielectric substrate has no
+    /// extinction `k`.
+    pub fn reflectance(&self, thickness: f64, cosine: f64, outside: f64, eta: f64, k: f64, wavelength: f64) -> f64 {
+        let n1 = Complex::real(outside);
+        let n2 = Complex::real(self.refraction_index);
+        let n3 = Complex::new(eta, k);
+
+        // Snell's law with complex cosines, which also covers total internal reflection.
+        let sin2 = Complex::real(outside * outside * (1.0 - cosine * cosine));
+        let cos1 = Complex::real(cosine);
+        let cos2 = (Complex::real(1.0) - sin2 / (n2 * n2)).sqrt();
+        let cos3 = (Complex::real(1.0) - sin2 / (n3 * n3)).sqrt();
+
+        // Phase difference between the two reflections after a round trip through the film.
+        let phase = Complex::real(4.0 * PI * thickness / wavelength) * n2 * cos2;
+        let shift = Complex::new(-phase.im, phase.re).exp();
+
+        let airy = |r12: Complex, r23: Complex| {
+            let r = (r12 + r23 * shift) / (Complex::real(1.0) + r12 * r23 * shift);
+            r.norm2()
+        };
+        let rs = airy(interface(n1, cos1, n2, cos2), interface(n2, cos2, n3, cos3));
+        let rp = airy(interface(n1, cos2, n2, cos1), interface(n2, cos3, n3, cos2));
+        ((rs + rp) / 2.0).min(1.0)
+    }
+}
+
+/// Fresnel amplitude reflection coefficient between media `a` and `b` for s-polarized light.
+/// Swapping the cosines gives the one for p-polarized light.
+fn interface(a: Complex, cos_a: Complex, b: Complex, cos_b: Complex) -> Complex {
+    (a * cos_a - b * cos_b) / (a * cos_a + b * cos_b)
+}
+
+#[derive(Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+
+    fn real(re: f64) -> Complex {
+        Complex::new(re, 0.0)
+    }
+
+    fn norm2(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// Principal square root, with a non-negative real part.
+    fn sqrt(self) -> Complex {
+        let norm = self.norm2().sqrt();
+        let re = ((norm + self.re) / 2.0).max(0.0).sqrt();
+        let im = ((norm - self.re) / 2.0).max(0.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    fn exp(self) -> Complex {
+        let scale = self.re.exp();
+        Complex::new(scale * self.im.cos(), scale * self.im.sin())
+    }
+}
+
+impl ops::Add for Complex {
+    type Output = Complex;
+
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl ops::Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl ops::Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(self.re * rhs.re - self.im * rhs.im, self.re * rhs.im + self.im * rhs.re)
+    }
+}
+
+impl ops::Div for Complex {
+    type Output = Complex;
+
+    fn div(self, rhs: Complex) -> Complex {
+        let norm2 = rhs.norm2();
+        Complex::new(
+            (self.re * rhs.re + self.im * rhs.im) / norm2,
+            (self.im * rhs.re - self.re * rhs.im) / norm2,
+        )
+    }
+}
@@ -0,0 +1,316 @@
+use aabb::AABB;
+use cgmath::{Point3, Vector3, Vector4, vec3};
+use cgmath::prelude::*;
+use hitable::{Hitable, HitRecord, next_object_id};
+use material::Material;
+use ray::Ray;
+use std::fmt;
+use std::sync::Arc;
+
+/// Triangle mesh with optional per vertex normals, texture coordinates and tangents. Faces are
+/// counter-clockwise seen from the front. Tangents are stored as in glTF, with the sign of the
+/// bitangent `cross(normal, tangent)` in `w`, and can be generated from the texture coordinates.
+pub struct Mesh {
+    pub positions: Vec<Point3<f64>>,
+    pub normals: Vec<Vector3<f64>>,
+    pub uvs: Vec<(f64, f64)>,
+    pub tangents: Vec<Vector4<f64>>,
+    pub faces: Vec<[usize; 3]>,
+}
+
+impl Mesh {
+    pub fn new(positions: Vec<Point3<f64>>, faces: Vec<[usize; 3]>) -> Mesh {
+        Mesh { positions, normals: vec![], uvs: vec![], tangents: vec![], faces }
+    }
+
+    pub fn with_normals(mut self, normals: Vec<Vector3<f64>>) -> Mesh {
+        self.normals = normals;
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<(f64, f64)>) -> Mesh {
+        self.uvs = uvs;
+        self
+    }
+
+    /// Derives tangents from the texture coordinates, averaging the directions in which `u`
+    /// and `v` increase over the faces around every vertex (Lengyel's method). Needs normals
+    /// and texture coordinates.
+    pub fn with_generated_tangents(mut self) -> Result<Mesh, MeshError> {
+        self.validate()?;
+        if self.normals.is_empty() || self.uvs.is_empty() {
+            return Err(MeshError::MissingAttributes("tangents need normals and texture coordinates"));
+        }
+        let mut dpdu = vec![Vector3::zero(); self.positions.len()];
+        let mut dpdv = vec![Vector3::zero(); self.positions.len()];
+        for face in &self.faces {
+            if let Some((du, dv)) = self.face_derivatives(face) {
+                for &i in face {
+                    dpdu[i] += du;
+                    dpdv[i] += dv;
+                }
+            }
+        }
+
+        self.tangents = self.normals.iter().zip(dpdu.iter().zip(&dpdv)).map(|(&n, (&du, &dv))| {
+            let tangent = du - n * n.dot(du);
+            if tangent.magnitude2() < 1e-24 {
+                return any_tangent(n).extend(1.0);
+            }
+            let sign = if n.cross(tangent).dot(dv) < 0.0 { -1.0 } else { 1.0 };
+            tangent.normalize().extend(sign)
+        }).collect();
+        Ok(self)
+    }
+
+    /// Turns the mesh into triangles for a BVH. All of them share one object id.
+    pub fn into_triangles(self, material: Arc<Material>) -> Result<Vec<Box<Hitable>>, MeshError> {
+        self.validate()?;
+        let mesh = Arc::new(self);
+        let object_id = next_object_id();
+        let material_id = material.id();
+        Ok((0..mesh.faces.len()).map(|face| {
+            Box::new(Triangle { mesh: mesh.clone(), face, material: material.clone(), object_id, material_id }) as Box<Hitable>
+        }).collect())
+    }
+
+    /// Checks that the faces only use existing vertices and that every vertex has each of
+    /// the optional attributes, if there are any.
+    fn validate(&self) -> Result<(), MeshError> {
+        let vertices = self.positions.len();
+        for (face, indices) in self.faces.iter().enumerate() {
+            if let Some(&index) = indices.iter().find(|&&i| i >= vertices) {
+                return Err(MeshError::IndexOutOfRange { face, index, vertices });
+            }
+        }
+        let attributes = [("normals", self.normals.len()), ("texture coordinates", self.uvs.len()), ("tangents", self.tangents.len())];
+        for &(attribute, count) in &attributes {
+            if count != 0 && count != vertices {
+                return Err(MeshError::AttributeCount { attribute, count, vertices });
+            }
+        }
+        Ok(())
+    }
+
+    /// Derivatives of the position with respect to the texture coordinates across a face, or
+    /// `None` if the face has no texture coordinates or they are degenerate.
+    fn face_derivatives(&self, face: &[usize; 3]) -> Option<(Vector3<f64>, Vector3<f64>)> {
+        if self.uvs.is_empty() {
+            return None;
+        }
+        let [i0, i1, i2] = *face;
+        let (du02, dv02) = (self.uvs[i0].0 - self.uvs[i2].0, self.uvs[i0].1 - self.uvs[i2].1);
+        let (du12, dv12) = (self.uvs[i1].0 - self.uvs[i2].0, self.uvs[i1].1 - self.uvs[i2].1);
+        let dp02 = self.positions[i0] - self.positions[i2];
+        let dp12 = self.positions[i1] - self.positions[i2];
+
+        let determinant = du02 * dv12 - dv02 * du12;
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+        Some(((dv12 * dp02 - dv02 * dp12) / determinant, (du02 * dp12 - du12 * dp02) / determinant))
+    }
+}
+
+/// Why a mesh can't be turned into triangles.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MeshError {
+    /// A face uses a vertex the mesh does not have.
+    IndexOutOfRange { face: usize, index: usize, vertices: usize },
+    /// An attribute is given for some vertices but not for all.
+    AttributeCount { attribute: &'static str, count: usize, vertices: usize },
+    /// Attributes needed for an operation are missing.
+    MissingAttributes(&'static str),
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MeshError::IndexOutOfRange { face, index, vertices } => {
+                write!(f, "face {} uses vertex {} of a mesh with {} vertices", face, index, vertices)
+            }
+            MeshError::AttributeCount { attribute, count, vertices } => {
+                write!(f, "mesh has {} {} for {} vertices", count, attribute, vertices)
+            }
+            MeshError::MissingAttributes(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Some direction perpendicular to `n`.
+fn any_tangent(n: Vector3<f64>) -> Vector3<f64> {
+    let axis = if n.x.abs() > 0.9 { vec3(0.0, 1.0, 0.0) } else { vec3(1.0, 0.0, 0.0) };
+    axis.cross(n).normalize()
+}
+
+/// A face of a mesh.
+pub struct Triangle {
+    mesh: Arc<Mesh>,
+    face: usize,
+    material: Arc<Material>,
+    object_id: usize,
+    material_id: usize,
+}
+
+impl Hitable for Triangle {
+    /// Möller-Trumbore intersection.
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mesh = &*self.mesh;
+        let face = mesh.faces[self.face];
+        let [p0, p1, p2] = [mesh.positions[face[0]], mesh.positions[face[1]], mesh.positions[face[2]]];
+        let e1 = p1 - p0;
+        let e2 = p2 - p0;
+
+        let pvec = ray.direction.cross(e2);
+        let determinant = e1.dot(pvec);
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+        let inverse = 1.0 / determinant;
+        let tvec = ray.origin - p0;
+        let b1 = tvec.dot(pvec) * inverse;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+        let qvec = tvec.cross(e1);
+        let b2 = ray.direction.dot(qvec) * inverse;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+        let t = e2.dot(qvec) * inverse;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+        let b0 = 1.0 - b1 - b2;
+
+        let interpolate = |a: Vector3<f64>, b: Vector3<f64>, c: Vector3<f64>| b0 * a + b1 * b + b2 * c;
+        let mut geometric_normal = e1.cross(e2).normalize();
+        let normal = if mesh.normals.is_empty() {
+            geometric_normal
+        } else {
+            let n = interpolate(mesh.normals[face[0]], mesh.normals[face[1]], mesh.normals[face[2]]).normalize();
+            // Vertex normals decide which side is the front.
+            if n.dot(geometric_normal) < 0.0 {
+                geometric_normal = -geometric_normal;
+            }
+            n
+        };
+
+        let uv = if mesh.uvs.is_empty() {
+            (b1, b2)
+        } else {
+            let (uv0, uv1, uv2) = (mesh.uvs[face[0]], mesh.uvs[face[1]], mesh.uvs[face[2]]);
+            (b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0, b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1)
+        };
+
+        let (mut dpdu, mut dpdv) = mesh.face_derivatives(&face).unwrap_or_else(|| {
+            let tangent = any_tangent(geometric_normal);
+            (tangent, geometric_normal.cross(tangent))
+        });
+        if !mesh.tangents.is_empty() {
+            let [t0, t1, t2] = [mesh.tangents[face[0]], mesh.tangents[face[1]], mesh.tangents[face[2]]];
+            let tangent = interpolate(t0.truncate(), t1.truncate(), t2.truncate());
+            let tangent = (tangent - normal * normal.dot(tangent)).normalize();
+            dpdu = dpdu.magnitude() * tangent;
+            dpdv = dpdv.magnitude() * t0.w.signum() * normal.cross(tangent);
+        }
+
+        let rec = HitRecord::new(ray, t, geometric_normal, uv, &self.material, self.object_id, self.material_id)
+            .with_derivatives(dpdu, dpdv)
+            .with_shading_normal(normal);
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<AABB> {
+        let face = self.mesh.faces[self.face];
+        let mut min = self.mesh.positions[face[0]];
+        let mut max = min;
+        for &i in &face[1..] {
+            let p = self.mesh.positions[i];
+            min = Point3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Point3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+        }
+        // Keep axis aligned faces from giving flat boxes, which rays would miss.
+        let padding = vec3(1e-4, 1e-4, 1e-4);
+        Some(AABB::new(min - padding, max + padding))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color::Color;
+    use material::Lambertian;
+
+    fn material() -> Arc<Material> {
+        Arc::new(Lambertian::new(Box::new(Color::white())))
+    }
+
+    /// Right triangle in the `xy` plane with its front facing `+z`.
+    fn triangle(uvs: Vec<(f64, f64)>) -> Box<Hitable> {
+        let positions = vec![Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0)];
+        let mesh = Mesh::new(positions, vec![[0, 1, 2]]).with_uvs(uvs);
+        mesh.into_triangles(material()).unwrap().pop().unwrap()
+    }
+
+    fn down_at(x: f64, y: f64) -> Ray {
+        Ray::new(Point3::new(x, y, 1.0), vec3(0.0, 0.0, -1.0), 0.0)
+    }
+
+    #[test]
+    fn hits_inside_and_misses_outside() {
+        let triangle = triangle(vec![]);
+        let rec = triangle.hit(&down_at(0.25, 0.5), 0.001, f64::MAX).expect("ray through the triangle missed");
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!((rec.p - Point3::new(0.25, 0.5, 0.0)).magnitude() < 1e-12);
+        assert!(rec.front_face);
+        assert!((rec.normal - vec3(0.0, 0.0, 1.0)).magnitude() < 1e-12);
+
+        assert!(triangle.hit(&down_at(0.6, 0.6), 0.001, f64::MAX).is_none());
+        assert!(triangle.hit(&down_at(-0.1, 0.5), 0.001, f64::MAX).is_none());
+        assert!(triangle.hit(&down_at(0.25, 0.5), 0.001, 0.5).is_none());
+        let parallel = Ray::new(Point3::new(-1.0, 0.2, 0.0), vec3(1.0, 0.0, 0.0), 0.0);
+        assert!(triangle.hit(&parallel, 0.001, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn interpolates_texture_coordinates() {
+        let rec = triangle(vec![]).hit(&down_at(0.25, 0.5), 0.001, f64::MAX).unwrap();
+        assert!((rec.u - 0.25).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+
+        let rec = triangle(vec![(0.5, 0.5), (1.0, 0.5), (0.5, 0.0)]).hit(&down_at(0.25, 0.5), 0.001, f64::MAX).unwrap();
+        assert!((rec.u - 0.625).abs() < 1e-12 && (rec.v - 0.25).abs() < 1e-12);
+        assert!((rec.dpdu - vec3(2.0, 0.0, 0.0)).magnitude() < 1e-12);
+        assert!((rec.dpdv - vec3(0.0, -2.0, 0.0)).magnitude() < 1e-12);
+    }
+
+    #[test]
+    fn tangent_sign_follows_mirrored_texture_coordinates() {
+        let positions = vec![Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0)];
+        let normals = vec![vec3(0.0, 0.0, 1.0); 3];
+        for &(v_sign, w) in &[(1.0, 1.0), (-1.0, -1.0)] {
+            let uvs = vec![(0.0, 0.0), (1.0, 0.0), (0.0, v_sign)];
+            let mesh = Mesh::new(positions.clone(), vec![[0, 1, 2]])
+                .with_normals(normals.clone())
+                .with_uvs(uvs)
+                .with_generated_tangents()
+                .unwrap();
+            for tangent in &mesh.tangents {
+                assert!((tangent.truncate() - vec3(1.0, 0.0, 0.0)).magnitude() < 1e-12);
+                assert_eq!(tangent.w, w);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_meshes() {
+        let positions = vec![Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0)];
+        let error = Mesh::new(positions.clone(), vec![[0, 1, 3]]).into_triangles(material()).err();
+        assert_eq!(error, Some(MeshError::IndexOutOfRange { face: 0, index: 3, vertices: 3 }));
+
+        let error = Mesh::new(positions.clone(), vec![[0, 1, 2]]).with_uvs(vec![(0.0, 0.0)]).into_triangles(material()).err();
+        assert_eq!(error, Some(MeshError::AttributeCount { attribute: "texture coordinates", count: 1, vertices: 3 }));
+
+        assert!(Mesh::new(positions, vec![[0, 1, 2]]).with_generated_tangents().is_err());
+    }
+}